@@ -0,0 +1,172 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use std::path::PathBuf;
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+#[derive(Serialize)]
+struct CocoImage {
+    id: usize,
+    file_name: String,
+    width: usize,
+    height: usize,
+    frame_index: usize,
+}
+
+#[derive(Serialize)]
+struct CocoAnnotation {
+    id: usize,
+    image_id: usize,
+    category_id: usize,
+    /// [x, y, width, height] in pixels.
+    bbox: [f32; 4],
+    area: f32,
+    iscrowd: u8,
+    track_id: usize,
+    /// All labels except the one used as category.
+    attributes: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+struct CocoCategory {
+    id: usize,
+    name: String,
+    supercategory: String,
+}
+
+#[derive(Serialize)]
+struct CocoDataset {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+fn label_value(label: &Label) -> Value {
+    match label {
+        Label::S(l) => Value::from(l.state.clone()),
+        Label::I(l) => Value::from(l.state),
+        Label::B(l) => Value::from(l.state),
+        Label::F(l) => Value::from(l.state),
+        Label::T(l) => Value::from(l.state.clone()),
+        Label::M(l) => Value::from(l.state.clone()),
+    }
+}
+
+impl LabelTask {
+    /// Export the annotations in COCO detection format, with one image entry per frame.
+    /// Interpolated boxes are written for every frame, invisible boxes are skipped.
+    /// The states of the string label named `class_label` (or the first string label) become the categories,
+    /// the index of the annotation sequence becomes the `track_id`. With `class_depth`, the taxonomy of the class label
+    /// is collapsed to that many levels. Boxes whose class label is not set are skipped (see `incomplete_report`).
+    pub fn export_coco<P: ImageProvider + ?Sized>(&self, path: &PathBuf, images: &P, class_label: Option<&str>, class_depth: Option<usize>) -> Result<(), String> {
+        let (class_index, class_config) = self.configs.class_label(class_label, class_depth)?;
+
+        // COCO ids start at 1.
+        let categories: Vec<CocoCategory> = class_config.paths().into_iter().enumerate()
+            .map(|(i, s)| CocoCategory { id: i + 1, name: s, supercategory: class_config.name.clone() })
+            .collect();
+
+        let mut dataset = CocoDataset { images: vec![], annotations: vec![], categories };
+
+        for frame in 0..images.frame_count() {
+            let [width, height] = images.frame_size(frame)?;
+            let image_id = frame + 1;
+            dataset.images.push(CocoImage {
+                id: image_id,
+                file_name: images.frame_name(frame).unwrap_or(format!("{}", frame)),
+                width,
+                height,
+                frame_index: frame,
+            });
+
+            for (track_id, a) in self.get_all_interpolated_annotations_for_frame(frame) {
+                if a.invisible {
+                    continue;
+                }
+
+                if a.labels.get(class_index).is_some_and(|l| !l.is_set()) {
+                    continue;
+                }
+
+                let mut category_id = None;
+                let mut attributes = Map::new();
+                for (label_index, label) in a.labels.iter().enumerate() {
+                    match label {
+                        Label::S(l) if label_index == class_index => {
+                            category_id = l.state.as_deref().and_then(|s| class_config.class_index(s)).map(|i| i + 1);
+                        },
+                        _ => {
+                            attributes.insert(label.name().to_string(), label_value(label));
+                        }
+                    }
+                }
+
+                let category_id = category_id.ok_or(format!(
+                    "Sequence {} has no valid '{}' label in frame {}.", track_id, class_config.name, frame))?;
+
+                let x = a.bbox.mins.x * width as f32;
+                let y = a.bbox.mins.y * height as f32;
+                let w = (a.bbox.maxs.x - a.bbox.mins.x) * width as f32;
+                let h = (a.bbox.maxs.y - a.bbox.mins.y) * height as f32;
+
+                dataset.annotations.push(CocoAnnotation {
+                    id: dataset.annotations.len() + 1,
+                    image_id,
+                    category_id,
+                    bbox: [x, y, w, h],
+                    area: w * h,
+                    iscrowd: 0,
+                    track_id,
+                    attributes,
+                });
+            }
+        }
+
+        let f = std::fs::File::create(path).map_err(|e| e.to_string())?;
+        serde_json::to_writer_pretty(f, &dataset).map_err(|e| e.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn export_coco_pixel_boxes() {
+        let mut task = LabelTask::new();
+        let mut a = add_box(&mut task, (0.25, 0.5), (0.75, 1.0), 0, "cat");
+
+        let images = TestImages { count: 2, size: [200, 100] };
+        let tmp = TempDir::new("export_coco");
+        let path = tmp.join("export.coco.json");
+        task.export_coco(&path, &images, None, None).unwrap();
+
+        let v: Value = serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(v["images"].as_array().unwrap().len(), 2);
+        assert_eq!(v["categories"].as_array().unwrap().len(), 3);
+        let anns = v["annotations"].as_array().unwrap();
+        // The box is extrapolated into the second frame.
+        assert_eq!(anns.len(), 2);
+        assert_eq!(anns[0]["bbox"], serde_json::json!([50.0, 50.0, 100.0, 50.0]));
+        assert_eq!(anns[0]["category_id"], 1);
+        assert_eq!(anns[1]["track_id"], 0);
+        assert_eq!(anns[0]["attributes"]["size"], Value::Null);
+
+        // Boxes without a class are skipped, an unknown class fails the export.
+        a.labels[0] = Label::S(LabelInstance::unset("animal"));
+        task.sequences[0].annotations[0] = a.clone();
+        task.export_coco(&path, &images, None, None).unwrap();
+        let v: Value = serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(v["annotations"].as_array().unwrap().is_empty());
+        assert_eq!(task.incomplete_report(None).unwrap(), "Required labels are not set in sequence 0 (frames [0]).");
+        a.labels[0] = Label::S(LabelInstance::new("animal", "emu".to_string()));
+        task.sequences[0].annotations[0] = a;
+        assert!(task.export_coco(&path, &images, None, None).is_err());
+    }
+}
@@ -1,10 +1,130 @@
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use std::sync::OnceLock;
+
+use super::keybindings::Keybindings;
+
+/// Separates the levels of a taxonomy in the state of a string label, e.g. "mammal/marsupial/possum".
+pub const STATE_PATH_SEPARATOR: char = '/';
+
+/// A state of a string label, or a group of more specific states in a taxonomy.
+/// In the config file, a state is a string and a group is a table with a `name` and `states`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum StateNode {
+    State(String),
+    Group { name: String, states: Vec<StateNode> },
+}
+
+impl StateNode {
+    pub fn name(&self) -> &str {
+        match self {
+            StateNode::State(name) => name,
+            StateNode::Group { name, .. } => name,
+        }
+    }
+}
+
+impl From<&str> for StateNode {
+    fn from(name: &str) -> Self {
+        StateNode::State(name.to_string())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LabelConfigString {
     pub name: String,
-    pub states: Vec<String>,
+    /// The states, which can be nested into a taxonomy. Groups can be selected as states too.
+    pub states: Vec<StateNode>,
     pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+}
+
+impl LabelConfigString {
+    /// The full paths of all states and groups, parents before their children, e.g. "mammal", "mammal/marsupial",
+    /// "mammal/marsupial/possum". Without groups, these are just the states.
+    pub fn paths(&self) -> Vec<String> {
+        fn add_paths(nodes: &[StateNode], parent: &str, paths: &mut Vec<String>) {
+            for node in nodes {
+                let path = join_state_path(parent, node.name());
+                paths.push(path.clone());
+                if let StateNode::Group { states, .. } = node {
+                    add_paths(states, &path, paths);
+                }
+            }
+        }
+        let mut paths = vec![];
+        add_paths(&self.states, "", &mut paths);
+        paths
+    }
+
+    /// Check that no state or group name contains `STATE_PATH_SEPARATOR`, which would make the paths ambiguous.
+    pub fn check_states(&self) -> Result<(), String> {
+        fn check(nodes: &[StateNode], label: &str) -> Result<(), String> {
+            for node in nodes {
+                if node.name().contains(STATE_PATH_SEPARATOR) {
+                    return Err(format!("The state '{}' of '{}' contains '{}'.", node.name(), label, STATE_PATH_SEPARATOR));
+                }
+                if let StateNode::Group { states, .. } = node {
+                    check(states, label)?;
+                }
+            }
+            Ok(())
+        }
+        check(&self.states, &self.name)
+    }
+
+    /// Index of `state` in `paths`, e.g. to use it as a class id.
+    pub fn state_index(&self, state: &str) -> Option<usize> {
+        self.paths().iter().position(|s| s == state)
+    }
+
+    /// Number of levels of the taxonomy, 1 if there are no groups.
+    pub fn depth(&self) -> usize {
+        fn depth(nodes: &[StateNode]) -> usize {
+            nodes.iter().map(|n| match n {
+                StateNode::State(_) => 1,
+                StateNode::Group { states, .. } => 1 + depth(states),
+            }).max().unwrap_or(0)
+        }
+        depth(&self.states)
+    }
+
+    /// The config with the taxonomy cut off below `depth` levels, e.g. to export coarse classes.
+    pub fn collapse(&self, depth: usize) -> LabelConfigString {
+        fn collapse(nodes: &[StateNode], depth: usize) -> Vec<StateNode> {
+            nodes.iter().map(|n| match n {
+                StateNode::Group { name, states } if depth > 1 => StateNode::Group { name: name.clone(), states: collapse(states, depth - 1) },
+                _ => StateNode::State(n.name().to_string()),
+            }).collect()
+        }
+        LabelConfigString { name: self.name.clone(), states: collapse(&self.states, depth.max(1)),
+            optional: self.optional, scope: self.scope, conditions: self.conditions.clone() }
+    }
+
+    /// Like `state_index`, but states below the depth of this config are mapped to their ancestor,
+    /// so that a collapsed config finds the coarse class of a fine state.
+    pub fn class_index(&self, state: &str) -> Option<usize> {
+        self.state_index(&truncate_state_path(state, self.depth()))
+    }
+}
+
+/// Path of the state `name` in the group with path `parent`, which is empty at the top level.
+pub fn join_state_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}{}{}", parent, STATE_PATH_SEPARATOR, name)
+    }
+}
+
+/// The first `depth` levels of the state path `state`.
+pub fn truncate_state_path(state: &str, depth: usize) -> String {
+    state.split(STATE_PATH_SEPARATOR).take(depth).collect::<Vec<_>>().join(&STATE_PATH_SEPARATOR.to_string())
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -13,6 +133,157 @@ pub struct LabelConfigInt {
     pub first: i32,
     pub last: i32,
     pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+}
+
+/// Any number of states that can apply at the same time, e.g. behaviours like "eating" and "walking".
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LabelConfigMulti {
+    pub name: String,
+    pub states: Vec<String>,
+    pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+}
+
+impl LabelConfigMulti {
+    /// Add `state` to or remove it from `selected`, which is kept in the order of `states`.
+    pub fn select(&self, selected: &mut Vec<String>, state: &str, on: bool) {
+        selected.retain(|s| s != state);
+        if on {
+            selected.push(state.to_string());
+            selected.sort_by_key(|s| self.states.iter().position(|t| t == s).unwrap_or(usize::MAX));
+        }
+    }
+}
+
+/// A yes/no flag like "occluded" or "truncated".
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LabelConfigBool {
+    pub name: String,
+    /// State of the flag in new annotations.
+    #[serde(default)]
+    pub default: bool,
+    pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+}
+
+/// A number in a continuous range, e.g. an estimated distance or a confidence.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LabelConfigFloat {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    /// Increment of the slider, 0 for no increment.
+    #[serde(default)]
+    pub step: f32,
+    pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+}
+
+/// Free text, e.g. the name of an individual animal or notes.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LabelConfigText {
+    pub name: String,
+    /// If set, the whole text must match this regular expression.
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// If set, the maximum number of characters.
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    pub optional: bool,
+    #[serde(default)]
+    pub scope: LabelScope,
+    #[serde(flatten)]
+    pub conditions: LabelConditions,
+    /// `regex`, compiled on first use, e.g. by `LabelConfigs::check` when the config is loaded.
+    #[serde(skip)]
+    pub compiled_regex: OnceLock<Result<Option<Regex>, String>>,
+}
+
+impl LabelConfigText {
+    /// The compiled `regex`, anchored to match the whole text. It is only compiled once, so `regex` must not change afterwards.
+    pub fn regex(&self) -> Result<Option<&Regex>, String> {
+        let compiled = self.compiled_regex.get_or_init(|| match &self.regex {
+            Some(r) => Regex::new(&format!("^(?:{})$", r))
+                .map(Some)
+                .map_err(|e| format!("Invalid regex for '{}': {}", self.name, e)),
+            None => Ok(None),
+        });
+        compiled.as_ref().map(Option::as_ref).map_err(Clone::clone)
+    }
+
+    /// Check `text` against `max_length` and `regex`. Returns a description of the problem, if any.
+    pub fn check(&self, text: &str) -> Result<(), String> {
+        if let Some(max_length) = self.max_length {
+            if text.chars().count() > max_length {
+                return Err(format!("'{}' is longer than {} characters for '{}'.", text, max_length, self.name));
+            }
+        }
+        if let Some(regex) = self.regex()? {
+            if !regex.is_match(text) {
+                return Err(format!("'{}' does not match '{}' for '{}'.", text, regex.as_str(), self.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A condition on the state of another label, e.g. `{ label = "animal", states = ["deer"] }`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LabelCondition {
+    /// Name of the other label.
+    pub label: String,
+    /// The condition holds if the other label has one of these states, written as by `Label::state_string`.
+    pub states: Vec<String>,
+}
+
+impl LabelCondition {
+    /// Whether the condition holds for an annotation with `labels`. A group of a taxonomy also matches the states in it,
+    /// a multi-select label matches if any of `states` is selected. It does not hold if the other label is not set.
+    pub fn holds(&self, labels: &[Label]) -> bool {
+        match labels.iter().find(|l| l.name() == self.label) {
+            Some(Label::S(LabelInstance { state: Some(state), .. })) =>
+                self.states.iter().any(|s| state == s || state.starts_with(&join_state_path(s, ""))),
+            Some(Label::M(LabelInstance { state: Some(states), .. })) => states.iter().any(|s| self.states.contains(s)),
+            Some(l) if l.is_set() => self.states.contains(&l.state_string()),
+            _ => false,
+        }
+    }
+}
+
+/// Conditions on other labels that decide whether a label applies. Every label type can have them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LabelConditions {
+    /// The label is only shown, and may only differ from its default, if this holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_if: Option<LabelCondition>,
+    /// An optional label is required if this holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_if: Option<LabelCondition>,
+}
+
+/// Whether a label belongs to the whole annotation sequence, e.g. the species, or can change from keyframe to keyframe,
+/// e.g. a behaviour.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelScope {
+    /// Stored once in `AnnotationSequence::labels`.
+    Track,
+    /// Stored in every keyframe.
+    #[default]
+    Frame,
 }
 
 /// LabelConfig which can represent optional labels.
@@ -28,45 +299,259 @@ impl LabelConfigOptional for LabelConfigInt {
     fn is_optional(&self) -> bool { self.optional }
 }
 
+impl LabelConfigOptional for LabelConfigMulti {
+    fn is_optional(&self) -> bool { self.optional }
+}
+
+impl LabelConfigOptional for LabelConfigBool {
+    fn is_optional(&self) -> bool { self.optional }
+}
+
+impl LabelConfigOptional for LabelConfigFloat {
+    fn is_optional(&self) -> bool { self.optional }
+}
+
+impl LabelConfigOptional for LabelConfigText {
+    fn is_optional(&self) -> bool { self.optional }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum LabelConfig {
     /// The bool is determining whether the label is optional.
     S(LabelConfigString),
     /// The bool is determining whether the label is optional.
     I(LabelConfigInt),
+    B(LabelConfigBool),
+    F(LabelConfigFloat),
+    T(LabelConfigText),
+    M(LabelConfigMulti),
+}
+
+impl LabelConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            LabelConfig::S(lcs) => &lcs.name,
+            LabelConfig::I(lci) => &lci.name,
+            LabelConfig::B(lcb) => &lcb.name,
+            LabelConfig::F(lcf) => &lcf.name,
+            LabelConfig::T(lct) => &lct.name,
+            LabelConfig::M(lcm) => &lcm.name,
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        match self {
+            LabelConfig::S(lcs) => lcs.is_optional(),
+            LabelConfig::I(lci) => lci.is_optional(),
+            LabelConfig::B(lcb) => lcb.is_optional(),
+            LabelConfig::F(lcf) => lcf.is_optional(),
+            LabelConfig::T(lct) => lct.is_optional(),
+            LabelConfig::M(lcm) => lcm.is_optional(),
+        }
+    }
+
+    pub fn scope(&self) -> LabelScope {
+        match self {
+            LabelConfig::S(lcs) => lcs.scope,
+            LabelConfig::I(lci) => lci.scope,
+            LabelConfig::B(lcb) => lcb.scope,
+            LabelConfig::F(lcf) => lcf.scope,
+            LabelConfig::T(lct) => lct.scope,
+            LabelConfig::M(lcm) => lcm.scope,
+        }
+    }
+
+    pub fn conditions(&self) -> &LabelConditions {
+        match self {
+            LabelConfig::S(lcs) => &lcs.conditions,
+            LabelConfig::I(lci) => &lci.conditions,
+            LabelConfig::B(lcb) => &lcb.conditions,
+            LabelConfig::F(lcf) => &lcf.conditions,
+            LabelConfig::T(lct) => &lct.conditions,
+            LabelConfig::M(lcm) => &lcm.conditions,
+        }
+    }
+
+    /// The label with the state of new annotations. All labels are unset, except flags, which have a `default`.
+    pub fn default_label(&self) -> Label {
+        match self {
+            LabelConfig::S(lcs) => Label::S(LabelInstance::unset(&lcs.name)),
+            LabelConfig::I(lci) => Label::I(LabelInstance::unset(&lci.name)),
+            LabelConfig::B(lcb) => Label::B(LabelInstance::new(&lcb.name, lcb.default)),
+            LabelConfig::F(lcf) => Label::F(LabelInstance::unset(&lcf.name)),
+            LabelConfig::T(lct) => Label::T(LabelInstance::unset(&lct.name)),
+            LabelConfig::M(lcm) => Label::M(LabelInstance::unset(&lcm.name)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LabelConfigs {
-    pub label_configs: Vec<LabelConfig>
+    pub label_configs: Vec<LabelConfig>,
+    /// Keyboard shortcuts, the defaults are used for everything that is not in the config file.
+    #[serde(default)]
+    pub keybindings: Keybindings,
+}
+
+impl LabelConfigs {
+    /// Check what can not be checked when parsing the config: key bindings, state names, regular expressions
+    /// and the labels referred to by conditions.
+    pub fn check(&self) -> Result<(), String> {
+        self.keybindings.shortcuts()?;
+        for c in &self.label_configs {
+            match c {
+                LabelConfig::S(lcs) => lcs.check_states()?,
+                LabelConfig::T(lct) => { lct.regex()?; },
+                _ => {},
+            }
+            let conditions = c.conditions();
+            for condition in conditions.visible_if.iter().chain(&conditions.required_if) {
+                if condition.label == c.name() || !self.label_configs.iter().any(|o| o.name() == condition.label) {
+                    return Err(format!("The condition of '{}' refers to an unknown label '{}'.", c.name(), condition.label));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the label at `index` applies to an annotation with `labels`, i.e. it has no `visible_if` condition
+    /// or the condition holds.
+    pub fn is_visible(&self, index: usize, labels: &[Label]) -> bool {
+        self.label_configs[index].conditions().visible_if.as_ref().is_none_or(|c| c.holds(labels))
+    }
+
+    /// Whether the label at `index` must be set in an annotation with `labels`: it applies, and it is not optional
+    /// or its `required_if` condition holds.
+    pub fn is_required(&self, index: usize, labels: &[Label]) -> bool {
+        let c = &self.label_configs[index];
+        self.is_visible(index, labels) && (!c.is_optional() || c.conditions().required_if.as_ref().is_some_and(|c| c.holds(labels)))
+    }
+
+    /// Names of the labels that are required (see `is_required`) but not set in an annotation with `labels`.
+    pub fn missing_labels(&self, labels: &[Label]) -> Vec<&str> {
+        let mut missing = vec![];
+        for (i, c) in self.label_configs.iter().enumerate() {
+            if self.is_required(i, labels) && !labels.get(i).is_some_and(Label::is_set) {
+                missing.push(c.name());
+            }
+        }
+        missing
+    }
+
+    /// Set the labels that do not apply (see `is_visible`) back to their defaults, e.g. after the label they depend on changed.
+    pub fn reset_hidden_labels(&self, labels: &mut [Label]) {
+        for i in 0..self.label_configs.len().min(labels.len()) {
+            if !self.is_visible(i, labels) {
+                labels[i] = self.label_configs[i].default_label();
+            }
+        }
+    }
+
+    /// The labels with scope 'track' with the state of new annotations, i.e. the `labels` of a new `AnnotationSequence`.
+    pub fn track_labels(&self) -> Vec<Label> {
+        self.label_configs.iter().filter(|c| c.scope() == LabelScope::Track).map(LabelConfig::default_label).collect()
+    }
+
+    /// Find the string label that is used as object class, e.g. for exporting to detector training formats.
+    /// If `name` is None, the first string label is used. If `depth` is given, the taxonomy of the label is
+    /// collapsed to that many levels (see `LabelConfigString::collapse`).
+    /// Returns the index of the label in `label_configs` and its configuration.
+    pub fn class_label(&self, name: Option<&str>, depth: Option<usize>) -> Result<(usize, LabelConfigString), String> {
+        for (i, c) in self.label_configs.iter().enumerate() {
+            if let LabelConfig::S(lcs) = c {
+                if name.is_none() || name == Some(lcs.name.as_str()) {
+                    return Ok((i, match depth {
+                        Some(depth) => lcs.collapse(depth),
+                        None => lcs.clone(),
+                    }));
+                }
+            }
+        }
+        match name {
+            Some(name) => Err(format!("No string label named '{}' in the label config.", name)),
+            None => Err("The label config does not contain a string label.".to_string()),
+        }
+    }
 }
 
 impl Default for LabelConfigs {
     fn default() -> Self {
         let l = LabelConfigString { name: "animal".to_string(), 
-        states: vec!["cat".to_string(),"dog".to_string(),"possum".to_string()],
-        optional: false };
+        states: vec!["cat".into(),"dog".into(),"possum".into()],
+        optional: false, scope: LabelScope::Frame, conditions: LabelConditions::default() };
 
-        let li = LabelConfigInt { name: "size".to_string(), first: 1, last: 10, optional: true };
+        let li = LabelConfigInt { name: "size".to_string(), first: 1, last: 10, optional: true, scope: LabelScope::Frame, conditions: LabelConditions::default() };
      
         Self { label_configs: vec![
             LabelConfig::S(l),
-            LabelConfig::I(li)] 
+            LabelConfig::I(li)],
+            keybindings: Keybindings::default(),
         }
     }
 
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct LabelInstance<T> {
     pub name: String,
-    pub state: T
+    /// None if the label is not set (yet), stored as null in the labels file.
+    pub state: Option<T>
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+impl<T> LabelInstance<T> {
+    pub fn new(name: &str, state: T) -> Self {
+        Self { name: name.to_string(), state: Some(state) }
+    }
+
+    pub fn unset(name: &str) -> Self {
+        Self { name: name.to_string(), state: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub enum Label {
     S(LabelInstance<String>),
-    I(LabelInstance<i32>)
+    I(LabelInstance<i32>),
+    B(LabelInstance<bool>),
+    F(LabelInstance<f32>),
+    T(LabelInstance<String>),
+    M(LabelInstance<Vec<String>>),
+}
+
+impl Label {
+    pub fn name(&self) -> &str {
+        match self {
+            Label::S(l) => &l.name,
+            Label::I(l) => &l.name,
+            Label::B(l) => &l.name,
+            Label::F(l) => &l.name,
+            Label::T(l) => &l.name,
+            Label::M(l) => &l.name,
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        match self {
+            Label::S(l) => l.state.is_some(),
+            Label::I(l) => l.state.is_some(),
+            Label::B(l) => l.state.is_some(),
+            Label::F(l) => l.state.is_some(),
+            Label::T(l) => l.state.is_some(),
+            Label::M(l) => l.state.is_some(),
+        }
+    }
+
+    /// The state of the label as text, e.g. for captions. Empty if the label is not set.
+    pub fn state_string(&self) -> String {
+        match self {
+            Label::S(l) => l.state.clone().unwrap_or_default(),
+            Label::I(l) => l.state.map(|s| s.to_string()).unwrap_or_default(),
+            Label::B(l) => l.state.map(|s| s.to_string()).unwrap_or_default(),
+            Label::F(l) => l.state.map(|s| s.to_string()).unwrap_or_default(),
+            Label::T(l) => l.state.clone().unwrap_or_default(),
+            Label::M(l) => l.state.as_ref().map(|s| s.join(", ")).unwrap_or_default(),
+        }
+    }
 }
 
 
@@ -83,11 +568,13 @@ mod tests {
     #[test]
     fn label_config_serialize() {
         let l = LabelConfigString { name: "ALabel".to_string(), 
-            states: vec!["One".to_string(),"Two".to_string(),"Three".to_string()],
-            optional: false,
+            states: vec!["One".into(),"Two".into(),"Three".into()],
+            optional: false, scope: LabelScope::Frame,
+            conditions: LabelConditions::default(),
         };
 
-        let li = LabelConfigInt { name: "ILabel".to_string(), first: 1, last: 5, optional: true };
+        let li = LabelConfigInt { name: "ILabel".to_string(), first: 1, last: 5, optional: true, scope: LabelScope::Track, conditions: LabelConditions::default() };
+        let lb = LabelConfigBool { name: "BLabel".to_string(), default: true, optional: false, scope: LabelScope::Frame, conditions: LabelConditions::default() };
 
         let s = toml::to_string(&l).unwrap();
         println!("{}", s);
@@ -95,9 +582,15 @@ mod tests {
         let lc = LabelConfigs { label_configs: vec![
             LabelConfig::S(l.clone()),
             LabelConfig::I(li.clone()),
-        ] };
+            LabelConfig::B(lb.clone()),
+        ], keybindings: Keybindings::default() };
         let s2 = toml::to_string(&lc).unwrap();
         println!("{}", s2);
+        let parsed: LabelConfigs = toml::from_str(&s2).unwrap();
+        assert!(matches!(&parsed.label_configs[2], LabelConfig::B(b) if b.name == "BLabel" && b.default));
+        assert!(s2.contains("scope = \"track\""));
+        assert_eq!(parsed.label_configs[1].scope(), LabelScope::Track);
+        assert_eq!(parsed.track_labels().len(), 1);
 
         let s3 = serde_json::to_string_pretty(&lc).unwrap();
         println!("JSON: {}", s3);
@@ -105,15 +598,131 @@ mod tests {
 
     #[test]
     fn label_serialize() {
-        let l = Label::S(LabelInstance::<String> {name: "ALabel".to_string(), state: "AState".to_string()});
+        let l = Label::S(LabelInstance::<String> {name: "ALabel".to_string(), state: Some("AState".to_string())});
         let s = toml::to_string(&l).unwrap();
         println!("{}", s);
 
         let s2 = serde_json::to_string_pretty(&l).unwrap();
         println!("{}", s2);
 
+        let b = Label::B(LabelInstance::<bool> {name: "occluded".to_string(), state: Some(true)});
+        let s3 = serde_json::to_string(&b).unwrap();
+        assert_eq!(s3, r#"{"B":{"name":"occluded","state":true}}"#);
+        assert!(serde_json::from_str::<Label>(&s3).unwrap() == b);
+
     }
     
+    #[test]
+    fn state_taxonomy() {
+        let toml_string = r#"
+            [[label_configs]]
+            [label_configs.S]
+            name = "animal"
+            states = ["bird", { name = "mammal", states = ["deer", { name = "marsupial", states = ["possum", "wombat"] }] }]
+            optional = false
+        "#;
+        let lc: LabelConfigs = toml::from_str(toml_string).unwrap();
+        assert!(lc.check().is_ok());
+        let (_, lcs) = lc.class_label(None, None).unwrap();
+        assert_eq!(lcs.paths(), vec!["bird", "mammal", "mammal/deer", "mammal/marsupial", "mammal/marsupial/possum", "mammal/marsupial/wombat"]);
+        assert_eq!(lcs.depth(), 3);
+        assert_eq!(lcs.state_index("mammal/marsupial/possum"), Some(4));
+        assert_eq!(lcs.state_index("possum"), None);
+
+        // Collapsed to two levels, possums are exported as marsupials.
+        let (_, coarse) = lc.class_label(None, Some(2)).unwrap();
+        assert_eq!(coarse.paths(), vec!["bird", "mammal", "mammal/deer", "mammal/marsupial"]);
+        assert_eq!(coarse.class_index("mammal/marsupial/possum"), Some(3));
+        assert_eq!(coarse.class_index("bird"), Some(0));
+        assert_eq!(lc.class_label(None, Some(1)).unwrap().1.paths(), vec!["bird", "mammal"]);
+
+        // The nested states are written back in the same form.
+        let parsed: LabelConfigs = toml::from_str(&toml::to_string(&lc).unwrap()).unwrap();
+        assert!(parsed.class_label(None, None).unwrap().1.states == lcs.states);
+
+        // A state with the separator in its name would collide with the path of a nested state.
+        let ambiguous: LabelConfigs = toml::from_str(&toml_string.replace("\"deer\"", "\"marsupial/possum\"")).unwrap();
+        assert!(ambiguous.check().is_err());
+    }
+
+    #[test]
+    fn label_conditions() {
+        let toml_string = r#"
+            [[label_configs]]
+            [label_configs.S]
+            name = "animal"
+            states = ["bird", { name = "mammal", states = ["deer", "possum"] }]
+            optional = false
+
+            [[label_configs]]
+            [label_configs.I]
+            name = "antler points"
+            first = 0
+            last = 20
+            optional = true
+            visible_if = { label = "animal", states = ["mammal/deer"] }
+
+            [[label_configs]]
+            [label_configs.B]
+            name = "pouch young"
+            optional = true
+            required_if = { label = "animal", states = ["mammal"] }
+        "#;
+        let lc: LabelConfigs = toml::from_str(toml_string).unwrap();
+        assert!(lc.check().is_ok());
+        let labels = |animal: &str| lc.label_configs.iter().map(|c| match c {
+            LabelConfig::S(lcs) => Label::S(LabelInstance::new(&lcs.name, animal.to_string())),
+            _ => c.default_label(),
+        }).collect::<Vec<_>>();
+
+        assert!(lc.is_visible(1, &labels("mammal/deer")) && !lc.is_visible(1, &labels("bird")));
+        assert!(!lc.is_required(1, &labels("mammal/deer")));
+        // A group matches all of its states.
+        assert!(lc.is_required(2, &labels("mammal/possum")) && !lc.is_required(2, &labels("bird")));
+
+        let parsed: LabelConfigs = toml::from_str(&toml::to_string(&lc).unwrap()).unwrap();
+        assert!(parsed.label_configs[1].conditions() == lc.label_configs[1].conditions());
+        assert!(parsed.label_configs[0].conditions().visible_if.is_none());
+
+        let mut unknown = lc.clone();
+        if let LabelConfig::B(lcb) = &mut unknown.label_configs[2] {
+            lcb.conditions.required_if.as_mut().unwrap().label = "species".to_string();
+        }
+        assert!(unknown.check().is_err());
+    }
+
+    #[test]
+    fn multi_label_select() {
+        let m = LabelConfigMulti { name: "behaviour".to_string(),
+            states: vec!["eating".to_string(), "walking".to_string(), "vocalizing".to_string()], optional: true, scope: LabelScope::Frame,
+            conditions: LabelConditions::default() };
+        let mut selected = vec![];
+        m.select(&mut selected, "vocalizing", true);
+        m.select(&mut selected, "eating", true);
+        m.select(&mut selected, "eating", true);
+        assert_eq!(selected, vec!["eating", "vocalizing"]);
+        m.select(&mut selected, "eating", false);
+        assert_eq!(selected, vec!["vocalizing"]);
+
+        let l = Label::M(LabelInstance::new("behaviour", selected));
+        assert_eq!(serde_json::to_string(&l).unwrap(), r#"{"M":{"name":"behaviour","state":["vocalizing"]}}"#);
+    }
+
+    #[test]
+    fn text_label_check() {
+        let text_config = |regex: &str| LabelConfigText { name: "id".to_string(), regex: Some(regex.to_string()), max_length: Some(4),
+            optional: false, scope: LabelScope::Frame, conditions: LabelConditions::default(), compiled_regex: OnceLock::new() };
+        let t = text_config("[A-Z][0-9]+");
+        assert!(t.check("A12").is_ok());
+        assert!(t.check("xA12").is_err());
+        assert!(t.check("A1234").is_err());
+        assert!(t.compiled_regex.get().is_some());
+        assert!(text_config("(").regex().is_err());
+
+        let lc: LabelConfigs = toml::from_str("[[label_configs]]\n[label_configs.T]\nname = \"notes\"\noptional = true\n").unwrap();
+        assert!(matches!(&lc.label_configs[0], LabelConfig::T(t) if t.regex.is_none() && t.check("anything").is_ok()));
+    }
+
     #[test]
     fn create_default_configs() {
         let c = LabelConfigs::default();
@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+/// Quote a CSV field if necessary.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl LabelTask {
+    /// Export the annotations as CSV with one row per box and frame, with interpolated boxes written out.
+    /// Columns are `frame,track_id,xmin,ymin,xmax,ymax,invisible,interpolated` followed by one column per label.
+    /// The states of multi-select labels are separated by ';', labels that are not set are empty.
+    /// Coordinates are normalized to [0,1]. As in `save_annotations`, only invisible keyframes are written, not the
+    /// interpolated invisible boxes following them.
+    pub fn export_csv<P: ImageProvider + ?Sized>(&self, path: &PathBuf, images: &P) -> Result<(), String> {
+        let mut header = vec!["frame", "track_id", "xmin", "ymin", "xmax", "ymax", "invisible", "interpolated"]
+            .into_iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        for c in &self.configs.label_configs {
+            header.push(csv_field(c.name()));
+        }
+
+        let mut csv = header.join(",") + "\n";
+        for frame in 0..images.frame_count() {
+            for (i, a) in self.get_all_interpolated_annotations_for_frame(frame) {
+                if a.invisible && a.interpolated {
+                    continue;
+                }
+                let mut row = vec![
+                    frame.to_string(), i.to_string(),
+                    a.bbox.mins.x.to_string(), a.bbox.mins.y.to_string(),
+                    a.bbox.maxs.x.to_string(), a.bbox.maxs.y.to_string(),
+                    a.invisible.to_string(), a.interpolated.to_string()];
+                for l in &a.labels {
+                    row.push(match l {
+                        Label::M(LabelInstance { state: Some(states), .. }) => csv_field(&states.join(";")),
+                        _ => csv_field(&l.state_string()),
+                    });
+                }
+                csv += &(row.join(",") + "\n");
+            }
+        }
+
+        fs::write(path, csv).map_err(|e| e.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn export_csv_rows() {
+        let mut task = LabelTask::new();
+        task.configs.label_configs.push(LabelConfig::T(LabelConfigText { name: "note, quoted".to_string(), regex: None, max_length: None,
+            optional: true, scope: LabelScope::Frame, conditions: LabelConditions::default(), compiled_regex: Default::default() }));
+        let mut a = add_box(&mut task, (0.0, 0.0), (0.5, 0.25), 0, "cat");
+        a.labels[2] = Label::T(LabelInstance::new("note, quoted", "says \"hi\"".to_string()));
+        task.sequences[0].edit_annotation(0, &a);
+        a.invisible = true;
+        task.sequences[0].edit_annotation(2, &a);
+
+        let images = TestImages { count: 4, size: [200, 100] };
+        let tmp = TempDir::new("export_csv");
+        let path = tmp.join("export.csv");
+        task.export_csv(&path, &images).unwrap();
+
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "frame,track_id,xmin,ymin,xmax,ymax,invisible,interpolated,animal,size,\"note, quoted\"");
+        assert_eq!(lines[1], "0,0,0,0,0.5,0.25,false,false,cat,,\"says \"\"hi\"\"\"");
+        assert_eq!(lines[2], "1,0,0,0,0.5,0.25,false,true,cat,,\"says \"\"hi\"\"\"");
+        // Only the invisible keyframe is written, not the invisible boxes after it.
+        assert_eq!(lines[3], "2,0,0,0,0.5,0.25,true,false,cat,,\"says \"\"hi\"\"\"");
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -0,0 +1,353 @@
+use quick_xml::escape::{escape, unescape};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use std::fs;
+use std::path::PathBuf;
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+/// Minimal XML element tree, enough for reading CVAT annotation files.
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn from_start(start: &BytesStart) -> Result<Self, String> {
+        let mut attributes = vec![];
+        for attr in start.attributes() {
+            let attr = attr.map_err(|e| e.to_string())?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+            let value = unescape(&String::from_utf8_lossy(&attr.value)).map_err(|e| e.to_string())?.to_string();
+            attributes.push((key, value));
+        }
+        Ok(Self {
+            name: String::from_utf8_lossy(start.name().as_ref()).to_string(),
+            attributes,
+            children: vec![],
+            text: String::new(),
+        })
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        let mut reader = Reader::from_str(s);
+        // The document root is a virtual element containing the real root element.
+        let mut stack = vec![XmlElement { name: String::new(), attributes: vec![], children: vec![], text: String::new() }];
+
+        loop {
+            let event = reader.read_event().map_err(|e| e.to_string())?;
+            let top = stack.len() - 1;
+            match event {
+                Event::Start(start) => stack.push(Self::from_start(&start)?),
+                Event::Empty(start) => {
+                    let element = Self::from_start(&start)?;
+                    stack[top].children.push(element);
+                },
+                Event::End(_) => {
+                    if top == 0 {
+                        return Err("Unexpected closing tag.".to_string());
+                    }
+                    let element = stack.pop().unwrap();
+                    stack[top - 1].children.push(element);
+                },
+                Event::Text(t) => stack[top].text += &t.decode().map_err(|e| e.to_string())?,
+                Event::CData(t) => stack[top].text += &t.decode().map_err(|e| e.to_string())?,
+                Event::GeneralRef(r) => {
+                    let entity = format!("&{};", r.decode().map_err(|e| e.to_string())?);
+                    stack[top].text += &unescape(&entity).map_err(|e| e.to_string())?;
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if stack.len() != 1 {
+            return Err("Unexpected end of file.".to_string());
+        }
+        stack.pop().unwrap().children.pop().ok_or("Empty XML document.".to_string())
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn parse_attribute<T: std::str::FromStr>(&self, name: &str) -> Result<T, String> {
+        let value = self.attribute(name).ok_or(format!("<{}> is missing the attribute '{}'.", self.name, name))?;
+        value.parse::<T>().map_err(|_| format!("<{}> has an invalid value '{}' for '{}'.", self.name, value, name))
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+/// CVAT flags are written as 0/1.
+fn flag(b: bool) -> u8 {
+    if b { 1 } else { 0 }
+}
+
+/// Index of the flag named `occluded`, which is mapped to the `occluded` flag of CVAT boxes instead of an attribute.
+fn occluded_index(configs: &LabelConfigs) -> Option<usize> {
+    configs.label_configs.iter().position(|c| matches!(c, LabelConfig::B(lcb) if lcb.name == "occluded"))
+}
+
+impl LabelTask {
+    /// Export the annotations in the "CVAT for video 1.1" XML format.
+    /// Every annotation sequence becomes a `<track>` whose label is the state of the string label named `class_label`
+    /// (or the first string label) at the first keyframe. All other labels are written as `<attribute>`s,
+    /// with one `<attribute>` per selected state for multi-select labels.
+    /// Boxes are written in pixels for every frame of the track, with `keyframe`, `outside` (invisible) and `occluded` flags.
+    /// The `occluded` flag is taken from the flag label named `occluded`, if there is one, instead of writing it as attribute.
+    /// Labels that are not set are left out, tracks without a class are skipped (see `incomplete_report`).
+    /// With `class_depth`, the taxonomy of the class label is collapsed to that many levels.
+    pub fn export_cvat<P: ImageProvider + ?Sized>(&self, path: &PathBuf, images: &P, class_label: Option<&str>, class_depth: Option<usize>) -> Result<(), String> {
+        let (class_index, class_config) = self.configs.class_label(class_label, class_depth)?;
+        let class_names = class_config.paths();
+        let occluded_index = occluded_index(&self.configs);
+        let frame_count = images.frame_count();
+
+        let mut xml = String::new();
+        xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<annotations>\n  <version>1.1</version>\n  <meta>\n    <task>\n";
+        xml += &format!("      <size>{}</size>\n      <mode>interpolation</mode>\n", frame_count);
+        xml += &format!("      <start_frame>0</start_frame>\n      <stop_frame>{}</stop_frame>\n", frame_count.max(1) - 1);
+
+        // Every CVAT label gets all labels except the class label as attributes.
+        let mut attributes = String::new();
+        for (i, c) in self.configs.label_configs.iter().enumerate() {
+            if i == class_index || Some(i) == occluded_index {
+                continue;
+            }
+            let (name, input_type, default_value, values) = match c {
+                LabelConfig::S(lcs) => (&lcs.name, "select", lcs.paths().first().cloned().unwrap_or_default(), lcs.paths().join("\n")),
+                LabelConfig::I(lci) => (&lci.name, "number", lci.first.to_string(), format!("{}\n{}\n1", lci.first, lci.last)),
+                LabelConfig::B(lcb) => (&lcb.name, "checkbox", lcb.default.to_string(), lcb.default.to_string()),
+                LabelConfig::F(lcf) => (&lcf.name, "number", lcf.min.to_string(), format!("{}\n{}\n{}", lcf.min, lcf.max, lcf.step)),
+                LabelConfig::T(lct) => (&lct.name, "text", String::new(), String::new()),
+                // CVAT has no multi-select attributes, the selected states are written as repeated attributes.
+                LabelConfig::M(lcm) => (&lcm.name, "select", lcm.states.first().cloned().unwrap_or_default(), lcm.states.join("\n")),
+            };
+            attributes += &format!("            <attribute>\n              <name>{}</name>\n              <mutable>True</mutable>\n", escape(name.as_str()));
+            attributes += &format!("              <input_type>{}</input_type>\n              <default_value>{}</default_value>\n", input_type, escape(default_value.as_str()));
+            attributes += &format!("              <values>{}</values>\n            </attribute>\n", escape(values.as_str()));
+        }
+
+        xml += "      <labels>\n";
+        for state in &class_names {
+            xml += &format!("        <label>\n          <name>{}</name>\n          <attributes>\n{}          </attributes>\n        </label>\n",
+                escape(state.as_str()), attributes);
+        }
+        xml += "      </labels>\n";
+        if frame_count > 0 {
+            let [width, height] = images.frame_size(0)?;
+            xml += &format!("      <original_size>\n        <width>{}</width>\n        <height>{}</height>\n      </original_size>\n", width, height);
+        }
+        xml += "    </task>\n  </meta>\n";
+
+        for (i, seq) in self.sequences.iter().enumerate() {
+            let first = match seq.annotations.first().and_then(|a| seq.get_interpolated_annotation_for_frame(a.frame)) {
+                Some(first) => first,
+                None => continue,
+            };
+            let label = match first.labels.get(class_index) {
+                Some(Label::S(LabelInstance { state: None, .. })) => continue,
+                Some(Label::S(l)) => l.state.as_deref().and_then(|s| class_config.class_index(s)).map(|c| &class_names[c]),
+                _ => None,
+            };
+            let label = label.ok_or(format!("Sequence {} has no valid '{}' label.", i, class_config.name))?;
+
+            xml += &format!("  <track id=\"{}\" label=\"{}\" source=\"manual\">\n", i, escape(label.as_str()));
+            for frame in first.frame..frame_count {
+                let a = match seq.get_interpolated_annotation_for_frame(frame) {
+                    // CVAT only stores the keyframe where the object leaves the frame.
+                    Some(a) if !(a.invisible && a.interpolated) => a,
+                    _ => continue,
+                };
+                let [width, height] = images.frame_size(frame)?;
+                let occluded = occluded_index.and_then(|i| a.labels.get(i))
+                    .is_some_and(|l| matches!(l, Label::B(LabelInstance { state: Some(true), .. })));
+
+                xml += &format!("    <box frame=\"{}\" outside=\"{}\" occluded=\"{}\" keyframe=\"{}\" xtl=\"{:.2}\" ytl=\"{:.2}\" xbr=\"{:.2}\" ybr=\"{:.2}\" z_order=\"0\">\n",
+                    frame, flag(a.invisible), flag(occluded), flag(!a.interpolated),
+                    a.bbox.mins.x * width as f32, a.bbox.mins.y * height as f32,
+                    a.bbox.maxs.x * width as f32, a.bbox.maxs.y * height as f32);
+                for (label_index, l) in a.labels.iter().enumerate() {
+                    let values = match l {
+                        _ if label_index == class_index || Some(label_index) == occluded_index || !l.is_set() => continue,
+                        Label::M(LabelInstance { state: Some(states), .. }) => states.clone(),
+                        _ => vec![l.state_string()],
+                    };
+                    for value in values {
+                        xml += &format!("      <attribute name=\"{}\">{}</attribute>\n", escape(l.name()), escape(value.as_str()));
+                    }
+                }
+                xml += "    </box>\n";
+            }
+            xml += "  </track>\n";
+        }
+        xml += "</annotations>\n";
+
+        fs::write(path, xml).map_err(|e| e.to_string())
+    }
+
+    /// Import annotations in the "CVAT for video 1.1" XML format, replacing all annotation sequences.
+    /// Every `<track>` becomes an annotation sequence, its label sets the state of the string label named `class_label`
+    /// (or the first string label). Only boxes marked as keyframes are imported, labelo interpolates in between.
+    /// `<attribute>`s are matched to the other labels by name, unknown attributes are ignored.
+    /// The `occluded` flag of the boxes sets the flag label named `occluded`, if there is one.
+    /// Repeated attributes of a multi-select label are collected into its states.
+    pub fn import_cvat<P: ImageProvider + ?Sized>(&mut self, path: &PathBuf, images: &P, class_label: Option<&str>) -> Result<(), String> {
+        let s = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let root = XmlElement::parse(&s)?;
+        if root.name != "annotations" {
+            return Err("Not a CVAT annotation file.".to_string());
+        }
+
+        let (class_index, class_config) = self.configs.class_label(class_label, None)?;
+        let occluded_index = occluded_index(&self.configs);
+        let mut sequences = vec![];
+
+        for track in root.children("track") {
+            let label = track.attribute("label").unwrap_or_default();
+            if class_config.state_index(label).is_none() {
+                return Err(format!("Track label '{}' is not a state of '{}'.", label, class_config.name));
+            }
+
+            let mut seq = AnnotationSequence::new();
+            for b in track.children("box") {
+                if b.attribute("keyframe") == Some("0") {
+                    continue;
+                }
+
+                let frame: usize = b.parse_attribute("frame")?;
+                let [width, height] = images.frame_size(frame)?;
+                let xtl: f32 = b.parse_attribute("xtl")?;
+                let ytl: f32 = b.parse_attribute("ytl")?;
+                let xbr: f32 = b.parse_attribute("xbr")?;
+                let ybr: f32 = b.parse_attribute("ybr")?;
+
+                let mut a = Annotation::new(&self.configs, xtl / width as f32, ytl / height as f32, frame);
+                a.bbox.maxs = SerializablePoint::new(xbr / width as f32, ybr / height as f32);
+                a.invisible = b.attribute("outside") == Some("1");
+                if let Label::S(l) = &mut a.labels[class_index] {
+                    l.state = Some(label.to_string());
+                }
+                if let Some(Label::B(l)) = occluded_index.and_then(|i| a.labels.get_mut(i)) {
+                    l.state = Some(b.attribute("occluded") == Some("1"));
+                }
+
+                for attr in b.children("attribute") {
+                    let name = attr.attribute("name").unwrap_or_default();
+                    let value = attr.text.trim();
+                    match a.labels.iter_mut().find(|l| l.name() == name) {
+                        Some(Label::S(l)) => l.state = Some(value.to_string()),
+                        Some(Label::I(l)) => {
+                            l.state = Some(value.parse().map_err(|_| format!("Invalid value '{}' for attribute '{}'.", value, name))?);
+                        },
+                        Some(Label::B(l)) => {
+                            l.state = Some(value.to_ascii_lowercase().parse().map_err(|_| format!("Invalid value '{}' for attribute '{}'.", value, name))?);
+                        },
+                        Some(Label::F(l)) => {
+                            l.state = Some(value.parse().map_err(|_| format!("Invalid value '{}' for attribute '{}'.", value, name))?);
+                        },
+                        Some(Label::T(l)) => l.state = Some(attr.text.clone()),
+                        Some(Label::M(l)) => {
+                            let states = l.state.get_or_insert_with(Vec::new);
+                            if !states.iter().any(|s| s == value) {
+                                states.push(value.to_string());
+                            }
+                        },
+                        None => {}
+                    }
+                }
+
+                seq.annotations.push(a);
+            }
+
+            seq.annotations.sort_by_key(|a| a.frame);
+            seq.annotations.dedup_by_key(|a| a.frame);
+            sequences.push(seq);
+        }
+
+        self.sequences = sequences;
+        self.update_track_labels();
+        self.current_sequence = 0;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn cvat_round_trip() {
+        let mut task = LabelTask::new();
+        task.configs.label_configs.push(LabelConfig::M(LabelConfigMulti { name: "behaviour".to_string(),
+            states: vec!["eating".to_string(), "walking".to_string()], optional: true, scope: LabelScope::Frame, conditions: LabelConditions::default() }));
+        task.configs.label_configs.push(LabelConfig::B(LabelConfigBool { name: "occluded".to_string(),
+            default: false, optional: true, scope: LabelScope::Frame, conditions: LabelConditions::default() }));
+        let mut a = add_box(&mut task, (0.1, 0.2), (0.3, 0.6), 1, "possum");
+        a.bbox.mins.x = 0.2;
+        if let Label::I(l) = &mut a.labels[1] {
+            l.state = Some(7);
+        }
+        if let Label::M(l) = &mut a.labels[2] {
+            l.state = Some(vec!["eating".to_string(), "walking".to_string()]);
+        }
+        a.labels[3] = Label::B(LabelInstance::new("occluded", true));
+        task.sequences[0].edit_annotation(3, &a);
+        a.invisible = true;
+        task.sequences[0].edit_annotation(4, &a);
+
+        let images = TestImages { count: 6, size: [100, 50] };
+        let tmp = TempDir::new("cvat_round_trip");
+        let path = tmp.join("annotations.xml");
+        task.export_cvat(&path, &images, None, None).unwrap();
+
+        let s = fs::read_to_string(&path).unwrap();
+        // Keyframes 1, 3, 4 and interpolated frame 2.
+        assert_eq!(s.matches("<box ").count(), 4);
+        assert!(s.contains("<box frame=\"3\" outside=\"0\" occluded=\"1\" keyframe=\"1\""));
+        assert!(s.contains("<box frame=\"4\" outside=\"1\" occluded=\"1\" keyframe=\"1\""));
+        // The occluded flag is not written as attribute.
+        assert!(!s.contains("occluded</name>") && !s.contains("<attribute name=\"occluded\">"));
+
+        let mut imported = LabelTask::new();
+        imported.configs = task.configs.clone();
+        imported.import_cvat(&path, &images, None).unwrap();
+        assert_eq!(imported.sequences.len(), 1);
+        let anns = &imported.sequences[0].annotations;
+        assert_eq!(anns.iter().map(|a| a.frame).collect::<Vec<_>>(), vec![1, 3, 4]);
+        assert!(!anns[1].invisible && anns[2].invisible);
+        assert!((anns[1].bbox.mins.x - 0.2).abs() < 1e-6);
+        match (&anns[0].labels[0], &anns[1].labels[1]) {
+            (Label::S(s), Label::I(i)) => {
+                assert_eq!(s.state.as_deref(), Some("possum"));
+                assert_eq!(i.state, Some(7));
+            },
+            _ => panic!("Unexpected label types."),
+        }
+        assert_eq!(anns[1].labels[2].state_string(), "eating, walking");
+        assert!(matches!(&anns[0].labels[3], Label::B(LabelInstance { state: Some(false), .. })));
+        assert!(matches!(&anns[1].labels[3], Label::B(LabelInstance { state: Some(true), .. })));
+
+        // Tracks without a class are skipped and reported, also if the class is optional.
+        if let LabelConfig::S(lcs) = &mut task.configs.label_configs[0] {
+            lcs.optional = true;
+        }
+        let mut b = Annotation::new(&task.configs, 0.5, 0.5, 2);
+        b.bbox.maxs = SerializablePoint::new(0.6, 0.6);
+        task.add_new_annotation_sequence(b);
+        task.export_cvat(&path, &images, None, None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().matches("<track ").count(), 1);
+        assert_eq!(task.incomplete_report(None).unwrap(), "Required labels are not set in sequence 1 (frames [2]).");
+    }
+}
@@ -0,0 +1,258 @@
+use super::labelo::*;
+
+/// Maximum number of edits that can be undone.
+const MAX_HISTORY: usize = 1000;
+
+/// A reversible change of the annotation sequences of a LabelTask.
+#[derive(Clone)]
+pub enum SequenceEdit {
+    /// The annotation sequence at `index` was changed from `before` to `after`.
+    Change { index: usize, before: AnnotationSequence, after: AnnotationSequence },
+    /// `sequence` was inserted at `index`.
+    Add { index: usize, sequence: AnnotationSequence },
+    /// `sequence` was removed from `index`.
+    Delete { index: usize, sequence: AnnotationSequence },
+    /// Several edits that are undone in one step, applied in this order.
+    Group(Vec<SequenceEdit>),
+}
+
+impl SequenceEdit {
+    fn apply(&self, task: &mut LabelTask) {
+        match self {
+            SequenceEdit::Change { index, after, .. } => {
+                task.sequences[*index] = after.clone();
+                task.current_sequence = *index;
+            },
+            SequenceEdit::Add { index, sequence } => {
+                task.sequences.insert(*index, sequence.clone());
+                task.current_sequence = *index;
+            },
+            SequenceEdit::Delete { index, .. } => {
+                task.sequences.remove(*index);
+                task.current_sequence = task.current_sequence.min(task.sequences.len().max(1) - 1);
+            },
+            SequenceEdit::Group(edits) => {
+                for edit in edits {
+                    edit.apply(task);
+                }
+            },
+        }
+    }
+
+    fn inverse(&self) -> SequenceEdit {
+        match self.clone() {
+            SequenceEdit::Change { index, before, after } => SequenceEdit::Change { index, before: after, after: before },
+            SequenceEdit::Add { index, sequence } => SequenceEdit::Delete { index, sequence },
+            SequenceEdit::Delete { index, sequence } => SequenceEdit::Add { index, sequence },
+            SequenceEdit::Group(edits) => SequenceEdit::Group(edits.iter().rev().map(|e| e.inverse()).collect()),
+        }
+    }
+}
+
+/// Undo/redo history of the edits of a LabelTask.
+/// Edits that span several updates, like dragging a box, are recorded with `begin` and `commit`,
+/// so that they can be undone in one step.
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<SequenceEdit>,
+    redo_stack: Vec<SequenceEdit>,
+    /// Index and state of the annotation sequence before the edit that is currently in progress.
+    pending: Option<(usize, AnnotationSequence)>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all edits, e.g. after loading different annotations.
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.pending = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Record an edit that has already been applied to the task.
+    pub fn record(&mut self, edit: SequenceEdit) {
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Start an edit of the annotation sequence at `index` that is committed later, e.g. when a drag is released.
+    pub fn begin(&mut self, task: &LabelTask, index: usize) {
+        if self.pending.is_none() && index < task.sequences.len() {
+            self.pending = Some((index, task.sequences[index].clone()));
+        }
+    }
+
+    /// Finish the edit started with `begin`. Nothing is recorded if the sequence did not change.
+    pub fn commit(&mut self, task: &LabelTask) {
+        if let Some((index, before)) = self.pending.take() {
+            if let Some(after) = task.sequences.get(index) {
+                if *after != before {
+                    self.record(SequenceEdit::Change { index, before, after: after.clone() });
+                }
+            }
+        }
+    }
+
+    /// Change the annotation sequence at `index` with `f` and record the change.
+    pub fn edit_sequence<F: FnOnce(&mut AnnotationSequence)>(&mut self, task: &mut LabelTask, index: usize, f: F) {
+        if index < task.sequences.len() {
+            self.begin(task, index);
+            f(&mut task.sequences[index]);
+            self.commit(task);
+        }
+    }
+
+    /// Append a new annotation sequence, make it the current one and record the change.
+    pub fn add_sequence(&mut self, task: &mut LabelTask, sequence: AnnotationSequence) {
+        let edit = SequenceEdit::Add { index: task.sequences.len(), sequence };
+        edit.apply(task);
+        self.record(edit);
+    }
+
+    /// Delete the annotation sequence at `index` and record the change.
+    pub fn delete_sequence(&mut self, task: &mut LabelTask, index: usize) {
+        if index < task.sequences.len() {
+            let edit = SequenceEdit::Delete { index, sequence: task.sequences[index].clone() };
+            edit.apply(task);
+            self.record(edit);
+        }
+    }
+
+    /// Split the annotation sequence at `index` at `frame` (see `LabelTask::split_sequence`) and record the change.
+    /// Returns false if the sequence could not be split.
+    pub fn split_sequence(&mut self, task: &mut LabelTask, index: usize, frame: usize) -> bool {
+        let before = match task.sequences.get(index) {
+            Some(s) => s.clone(),
+            None => return false,
+        };
+        match task.split_sequence(index, frame) {
+            Some(second) => {
+                self.record(SequenceEdit::Group(vec![
+                    SequenceEdit::Change { index, before, after: task.sequences[index].clone() },
+                    SequenceEdit::Add { index: second, sequence: task.sequences[second].clone() },
+                ]));
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Merge the annotation sequence `other` into the one at `index` (see `LabelTask::merge_sequences`) and record the change.
+    /// Returns the number of keyframes of `other` that were dropped, or None if nothing was merged.
+    pub fn merge_sequences(&mut self, task: &mut LabelTask, index: usize, other: usize) -> Option<usize> {
+        let (before, other_sequence) = (task.sequences.get(index)?.clone(), task.sequences.get(other)?.clone());
+        let (merged, dropped) = task.merge_sequences(index, other)?;
+        // The change is recorded before the deletion, since the merged sequence keeps its index until then.
+        self.record(SequenceEdit::Group(vec![
+            SequenceEdit::Change { index, before, after: task.sequences[merged].clone() },
+            SequenceEdit::Delete { index: other, sequence: other_sequence },
+        ]));
+        Some(dropped)
+    }
+
+    /// Revert the last edit. Returns false if there was nothing to undo.
+    pub fn undo(&mut self, task: &mut LabelTask) -> bool {
+        self.commit(task);
+        if let Some(edit) = self.undo_stack.pop() {
+            edit.inverse().apply(task);
+            self.redo_stack.push(edit);
+            return true;
+        }
+        false
+    }
+
+    /// Apply the last undone edit again. Returns false if there was nothing to redo.
+    pub fn redo(&mut self, task: &mut LabelTask) -> bool {
+        if let Some(edit) = self.redo_stack.pop() {
+            edit.apply(task);
+            self.undo_stack.push(edit);
+            return true;
+        }
+        false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_redo() {
+        let mut task = LabelTask::new();
+        let mut history = History::new();
+
+        history.add_sequence(&mut task, AnnotationSequence::new());
+        let a = Annotation::new(&task.configs, 0.1, 0.1, 0);
+
+        // Several changes between begin and commit are one step, like a drag.
+        history.begin(&task, 0);
+        task.sequences[0].annotations.push(a.clone());
+        for x in [0.2, 0.3, 0.4] {
+            let mut b = a.clone();
+            b.bbox.maxs.x = x;
+            task.sequences[0].edit_annotation(0, &b);
+        }
+        history.commit(&task);
+        // Unchanged sequences are not recorded.
+        history.edit_sequence(&mut task, 0, |_| {});
+
+        history.add_sequence(&mut task, AnnotationSequence::new());
+        history.delete_sequence(&mut task, 0);
+        assert_eq!(task.sequences.len(), 1);
+        assert!(task.sequences[0].annotations.is_empty());
+
+        assert!(history.undo(&mut task));
+        assert_eq!(task.sequences.len(), 2);
+        assert_eq!(task.sequences[0].annotations[0].bbox.maxs.x, 0.4);
+        assert!(history.undo(&mut task));
+        assert_eq!(task.sequences.len(), 1);
+        assert!(history.undo(&mut task));
+        assert!(task.sequences[0].annotations.is_empty());
+        assert!(history.undo(&mut task));
+        assert!(task.sequences.is_empty());
+        assert!(!history.undo(&mut task));
+
+        assert!(history.redo(&mut task));
+        assert!(history.redo(&mut task));
+        assert_eq!(task.sequences[0].annotations[0].bbox.maxs.x, 0.4);
+
+        // A new edit drops the redo history.
+        history.add_sequence(&mut task, AnnotationSequence::new());
+        assert!(!history.can_redo());
+
+        // Splitting and merging are undone in one step.
+        let mut task = LabelTask::new();
+        let mut history = History::new();
+        task.add_new_annotation_sequence(a.clone());
+        let mut b = a.clone();
+        b.frame = 10;
+        task.sequences[0].annotations.push(b);
+        let original = task.sequences.clone();
+        assert!(history.split_sequence(&mut task, 0, 5));
+        assert_eq!(task.sequences.len(), 2);
+        assert_eq!(history.merge_sequences(&mut task, 1, 0), Some(0));
+        assert_eq!(task.sequences.len(), 1);
+        assert!(history.undo(&mut task));
+        assert_eq!(task.sequences.len(), 2);
+        assert!(history.undo(&mut task));
+        assert!(task.sequences == original);
+        assert!(history.redo(&mut task));
+        assert!(history.redo(&mut task));
+        assert_eq!(task.sequences.len(), 1);
+    }
+}
@@ -15,15 +15,54 @@ use std::sync::{
     RwLock};
 use std::marker::{Sync, Send};
 
-pub trait ImageProvider 
-    where Self: Sized {
-    fn from_path(path: path::PathBuf) -> Result<Self, String>;
+pub trait ImageProvider {
+    fn from_path(path: path::PathBuf) -> Result<Self, String>
+        where Self: Sized;
     fn frame_count(&self) -> usize;
     fn get_frame(&mut self, frame: usize, texture_handle: &mut Option<egui::TextureHandle>, ctx: &egui::Context);
+    /// Name of the frame, e.g. the image file name, used to reference the frame from exported label files.
+    fn frame_name(&self, frame: usize) -> Option<String>;
+    /// Size of the frame in pixels as [width, height].
+    fn frame_size(&self, frame: usize) -> Result<[usize; 2], String>;
 }
 
-const CACHE_COUNT: u64 = 100;
-const PREDICTIVE_LOADING_IMAGE_COUNT: usize = 25;
+/// File extensions that are opened as video files, if labelo is built with the `video` feature.
+pub const VIDEO_EXTENSIONS: [&str; 10] = ["mp4", "m4v", "mkv", "webm", "avi", "mov", "mpg", "mpeg", "ts", "wmv"];
+
+/// Open a directory of images, or a video file if labelo is built with the `video` feature.
+pub fn open_image_provider(path: path::PathBuf) -> Result<Box<dyn ImageProvider>, String> {
+    if path.is_dir() {
+        return Ok(Box::new(ImageDirectory::from_path(path)?));
+    }
+
+    let is_video = path.extension()
+        .is_some_and(|e| VIDEO_EXTENSIONS.iter().any(|v| e.eq_ignore_ascii_case(v)));
+    if is_video {
+        #[cfg(feature = "video")]
+        return Ok(Box::new(super::video::VideoFile::from_path(path)?));
+        #[cfg(not(feature = "video"))]
+        return Err("Opening video files requires labelo to be built with the 'video' feature.".to_string());
+    }
+
+    Err("Not a directory.".to_string())
+}
+
+/// Show `img` in the texture, creating the texture if there is none or the size changed.
+pub(super) fn set_texture(texture_handle: &mut Option<egui::TextureHandle>, img: Arc<egui::ColorImage>,
+                          texture_options: egui::TextureOptions, ctx: &egui::Context) {
+    if let Some(texture_handle) = texture_handle {
+        if texture_handle.size()[0] != img.width() || texture_handle.size()[1] != img.height() {
+            *texture_handle = ctx.load_texture("videoframe", img, texture_options);
+        } else {
+            texture_handle.set(img, texture_options);
+        }
+    } else {
+        *texture_handle = Some(ctx.load_texture("videoframe", img, texture_options));
+    }
+}
+
+pub(super) const CACHE_COUNT: u64 = 100;
+pub(super) const PREDICTIVE_LOADING_IMAGE_COUNT: usize = 25;
 pub struct ImageDirectory {
     path: path::PathBuf,
     image_count: usize,
@@ -124,17 +163,26 @@ impl ImageProvider for ImageDirectory {
         };
         
         if let Some(img) = img {
-            if let Some(texture_handle) = texture_handle {
-                if texture_handle.size()[0] != img.width() || texture_handle.size()[1] != img.height() {
-                    *texture_handle = ctx.load_texture("videoframe", img, self.texture_options);
-                } else {
-                    texture_handle.set(img, self.texture_options);
-                }
-            } else {
-                *texture_handle = Some(ctx.load_texture("videoframe", img, self.texture_options));
-            }
+            set_texture(texture_handle, img, self.texture_options, ctx);
         }
     }
+
+    fn frame_name(&self, frame: usize) -> Option<String> {
+        let filenames = self.image_filenames.read().unwrap();
+        filenames.get(frame)
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+    }
+
+    fn frame_size(&self, frame: usize) -> Result<[usize; 2], String> {
+        if let Some(img) = self.image_cache.get(&frame) {
+            return Ok(img.size);
+        }
+        let filenames = self.image_filenames.read().unwrap();
+        let path = filenames.get(frame).ok_or(format!("Frame {} does not exist.", frame))?;
+        let (w, h) = image::image_dimensions(path).map_err(|e| e.to_string())?;
+        Ok([w as usize, h as usize])
+    }
 }
 
 impl Drop for ImageDirectory {
@@ -192,8 +240,8 @@ impl ImageDirectory {
                     receiver.recv()
                 };
 
+                // The sender is only dropped when the image directory is dropped, so this is a normal shutdown.
                 if let Err(_result) = result {
-                    println!("Receive error, predictive loader is stopping.");
                     return ();
                 }
 
@@ -267,4 +315,35 @@ impl ImageDirectory {
 
         self.predictive_loading_thread = Some(t);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Image provider without any image data, for testing exporters.
+    pub(crate) struct TestImages {
+        pub count: usize,
+        pub size: [usize; 2],
+    }
+
+    impl ImageProvider for TestImages {
+        fn from_path(_path: path::PathBuf) -> Result<Self, String> {
+            Err("Not supported.".to_string())
+        }
+
+        fn frame_count(&self) -> usize {
+            self.count
+        }
+
+        fn get_frame(&mut self, _frame: usize, _texture_handle: &mut Option<egui::TextureHandle>, _ctx: &egui::Context) {}
+
+        fn frame_name(&self, frame: usize) -> Option<String> {
+            Some(format!("{:05}.png", frame))
+        }
+
+        fn frame_size(&self, _frame: usize) -> Result<[usize; 2], String> {
+            Ok(self.size)
+        }
+    }
+}
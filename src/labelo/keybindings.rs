@@ -0,0 +1,166 @@
+use egui::{Event, InputState, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Actions that can be bound to keys in the `[keybindings]` table of the label config file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAction {
+    PreviousFrame,
+    NextFrame,
+    PreviousKeyframe,
+    NextKeyframe,
+    PreviousSequence,
+    NextSequence,
+    NewSequence,
+    DeleteKeyframe,
+    ToggleInvisible,
+    PlayPause,
+    Save,
+    Quit,
+    Undo,
+    Redo,
+    /// Set the first string label to the state with this index.
+    LabelState(usize),
+}
+
+/// Keyboard shortcuts like "Ctrl+Shift+Z", stored in the `[keybindings]` table of the label config file.
+/// Key names are those of egui, e.g. "A", "1", "ArrowLeft", "Delete", "PageUp" or "F1".
+/// Modifiers are "Ctrl", "Shift", "Alt" and "Cmd" (Command on Mac, Ctrl elsewhere).
+/// An empty string disables the action.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Keybindings {
+    pub previous_frame: String,
+    pub next_frame: String,
+    pub previous_keyframe: String,
+    pub next_keyframe: String,
+    pub previous_sequence: String,
+    pub next_sequence: String,
+    pub new_sequence: String,
+    pub delete_keyframe: String,
+    pub toggle_invisible: String,
+    pub play_pause: String,
+    pub save: String,
+    pub quit: String,
+    pub undo: String,
+    pub redo: String,
+    /// Keys selecting the first, second, ... state of the first string label.
+    pub label_states: Vec<String>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            previous_frame: "ArrowLeft".to_string(),
+            next_frame: "ArrowRight".to_string(),
+            previous_keyframe: "Shift+ArrowLeft".to_string(),
+            next_keyframe: "Shift+ArrowRight".to_string(),
+            previous_sequence: "ArrowUp".to_string(),
+            next_sequence: "ArrowDown".to_string(),
+            new_sequence: "N".to_string(),
+            delete_keyframe: "Delete".to_string(),
+            toggle_invisible: "I".to_string(),
+            play_pause: "P".to_string(),
+            save: "Ctrl+S".to_string(),
+            quit: "Ctrl+Q".to_string(),
+            undo: "Ctrl+Z".to_string(),
+            redo: "Ctrl+Shift+Z".to_string(),
+            label_states: (1..=9).map(|i| i.to_string()).collect(),
+        }
+    }
+}
+
+/// Parse a shortcut like "Ctrl+Shift+Z". Returns None for an empty string.
+pub fn parse_shortcut(s: &str) -> Result<Option<KeyboardShortcut>, String> {
+    if s.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut modifiers = Modifiers::NONE;
+    // Split at the last '+', so that "Ctrl++" binds the plus key.
+    let (modifier_names, key_name) = match s.trim().rsplit_once('+') {
+        Some((m, "")) => (m.strip_suffix('+').unwrap_or(m), "+"),
+        Some((m, k)) => (m, k),
+        None => ("", s.trim()),
+    };
+    for m in modifier_names.split('+').map(str::trim).filter(|m| !m.is_empty()) {
+        modifiers = modifiers | match m.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "shift" => Modifiers::SHIFT,
+            "alt" | "option" => Modifiers::ALT,
+            "cmd" | "command" => Modifiers::COMMAND,
+            _ => return Err(format!("Unknown modifier '{}' in key binding '{}'.", m, s)),
+        };
+    }
+    let key = Key::from_name(key_name.trim())
+        .ok_or(format!("Unknown key '{}' in key binding '{}'.", key_name, s))?;
+    Ok(Some(KeyboardShortcut::new(modifiers, key)))
+}
+
+impl Keybindings {
+    /// All shortcuts with their actions. Fails on the first binding that can not be parsed.
+    pub fn shortcuts(&self) -> Result<Vec<(KeyboardShortcut, KeyAction)>, String> {
+        let mut bindings = vec![
+            (&self.previous_frame, KeyAction::PreviousFrame),
+            (&self.next_frame, KeyAction::NextFrame),
+            (&self.previous_keyframe, KeyAction::PreviousKeyframe),
+            (&self.next_keyframe, KeyAction::NextKeyframe),
+            (&self.previous_sequence, KeyAction::PreviousSequence),
+            (&self.next_sequence, KeyAction::NextSequence),
+            (&self.new_sequence, KeyAction::NewSequence),
+            (&self.delete_keyframe, KeyAction::DeleteKeyframe),
+            (&self.toggle_invisible, KeyAction::ToggleInvisible),
+            (&self.play_pause, KeyAction::PlayPause),
+            (&self.save, KeyAction::Save),
+            (&self.quit, KeyAction::Quit),
+            (&self.undo, KeyAction::Undo),
+            (&self.redo, KeyAction::Redo),
+        ];
+        for (i, key) in self.label_states.iter().enumerate() {
+            bindings.push((key, KeyAction::LabelState(i)));
+        }
+
+        let mut shortcuts = vec![];
+        for (binding, action) in bindings {
+            if let Some(shortcut) = parse_shortcut(binding)? {
+                shortcuts.push((shortcut, action));
+            }
+        }
+        Ok(shortcuts)
+    }
+}
+
+/// Actions whose shortcuts were pressed (or repeated by holding the key) since the last frame.
+/// Modifiers must match exactly, so "ArrowLeft" does not trigger on Shift+ArrowLeft.
+pub fn pressed_actions(input: &InputState, shortcuts: &[(KeyboardShortcut, KeyAction)]) -> Vec<KeyAction> {
+    let mut actions = vec![];
+    for event in &input.events {
+        if let Event::Key { key, pressed: true, modifiers, .. } = event {
+            for (shortcut, action) in shortcuts {
+                if shortcut.logical_key == *key && modifiers.matches_exact(shortcut.modifiers) {
+                    actions.push(*action);
+                }
+            }
+        }
+    }
+    actions
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_shortcuts() {
+        assert_eq!(parse_shortcut("Ctrl+Shift+Z"), Ok(Some(KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::Z))));
+        assert_eq!(parse_shortcut("ArrowLeft"), Ok(Some(KeyboardShortcut::new(Modifiers::NONE, Key::ArrowLeft))));
+        assert_eq!(parse_shortcut("ctrl + 1"), Ok(Some(KeyboardShortcut::new(Modifiers::CTRL, Key::Num1))));
+        assert_eq!(parse_shortcut("Ctrl++"), Ok(Some(KeyboardShortcut::new(Modifiers::CTRL, Key::Plus))));
+        assert_eq!(parse_shortcut(""), Ok(None));
+        assert!(parse_shortcut("Hyper+A").is_err());
+        assert!(parse_shortcut("Ctrl+Nope").is_err());
+
+        let shortcuts = Keybindings::default().shortcuts().unwrap();
+        assert!(shortcuts.contains(&(KeyboardShortcut::new(Modifiers::NONE, Key::Num9), KeyAction::LabelState(8))));
+    }
+}
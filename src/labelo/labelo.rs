@@ -54,14 +54,19 @@ impl LabelTask {
     }
 
     /// Saves annotations for every frame up to and not including `frame_count`.
+    /// The file is written to a temporary file first and then renamed, so `path` is never left half written.
     pub fn save_annotations(&self, path: &PathBuf, frame_count: usize, save_only_visible: bool) -> Result<(), String> {
-        let f = std::fs::File::create(path);
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        let f = std::fs::File::create(&tmp_path);
         if let Ok(f) = f {
 
             let mut full: Vec<AnnotationSequence> = vec![];
             // create full annotation sequences
             for seq in &self.sequences {
                 let mut s = AnnotationSequence::new();
+                s.labels = seq.labels.clone();
                 for frame in 0..frame_count {
                     let a = seq.get_interpolated_annotation_for_frame(frame);
                     if let Some(a) = a {
@@ -74,13 +79,17 @@ impl LabelTask {
                         }
                     }
                 }
+                s.strip_track_labels();
                 full.push(s);
             }
 
-            if let Err(e) = serde_json::to_writer_pretty(f, &full) {
+            if let Err(e) = serde_json::to_writer_pretty(&f, &full) {
                 return Err(e.to_string());
             }
-            return Ok(());
+            if let Err(e) = f.sync_all() {
+                return Err(e.to_string());
+            }
+            return std::fs::rename(&tmp_path, path).map_err(|e| e.to_string());
         }
         return Err("Could not open output file.".to_string());
     }
@@ -97,6 +106,7 @@ impl LabelTask {
             
             if !load_only_keyframes {
                 self.sequences = full;
+                self.update_track_labels();
                 return Ok(());
             }
 
@@ -104,6 +114,7 @@ impl LabelTask {
             self.sequences.clear();
             for seq in &full {
                 let mut s = AnnotationSequence::new();
+                s.labels = seq.labels.clone();
                 for l in &seq.annotations {
                     if !l.interpolated {
                         s.annotations.push(l.clone());
@@ -111,6 +122,7 @@ impl LabelTask {
                 }
                 self.sequences.push(s);
             }
+            self.update_track_labels();
             return Ok(());
         }
         return Err("Could not open input file.".to_string());
@@ -128,12 +140,29 @@ impl LabelTask {
             if result.is_err() {
                 return Err(result.err().unwrap().message().to_string());
             }
-            self.configs = result.unwrap();
+            let configs = result.unwrap();
+            configs.check()?;
+            self.configs = configs;
+            self.update_track_labels();
             return Ok(());
         }
         return Err(f.err().unwrap().to_string());
     }
 
+    /// Bring the track labels of all annotation sequences in line with the label configs (see `AnnotationSequence::update_track_labels`).
+    pub fn update_track_labels(&mut self) {
+        for s in &mut self.sequences {
+            s.update_track_labels(&self.configs);
+        }
+    }
+
+    /// An empty annotation sequence with the track labels of the label configs in their initial state.
+    pub fn new_sequence(&self) -> AnnotationSequence {
+        let mut s = AnnotationSequence::new();
+        s.labels = self.configs.track_labels();
+        s
+    }
+
     /// Get the annotation for the current annotation sequence for a particular frame.
     /// This can return None.
     pub fn get_current_interpolated_annotation_for_frame(&self, frame: usize) -> Option<Annotation> {
@@ -149,6 +178,34 @@ impl LabelTask {
         self.sequences.len() > 0
     }
 
+    /// A description of the annotation sequences and frames in which required labels are not set,
+    /// e.g. to warn when exporting. None if all annotation sequences are complete.
+    /// The exporters skip boxes without a class, so the string label named `class_label` (or the first string label)
+    /// counts as required here even if it is optional.
+    pub fn incomplete_report(&self, class_label: Option<&str>) -> Option<String> {
+        let class_index = self.configs.class_label(class_label, None).ok().map(|(i, _)| i);
+        let incomplete: Vec<String> = self.sequences.iter().enumerate()
+            .filter_map(|(i, s)| {
+                let mut frames = s.incomplete_frames(&self.configs);
+                if let Some(class_index) = class_index {
+                    let unclassified: Vec<usize> = s.annotations.iter()
+                        .filter(|a| {
+                            let mut labels = a.labels.clone();
+                            s.apply_track_labels(&mut labels);
+                            !a.invisible && !labels.get(class_index).is_some_and(Label::is_set)
+                        })
+                        .map(|a| a.frame)
+                        .collect();
+                    frames.extend(unclassified);
+                    frames.sort();
+                    frames.dedup();
+                }
+                (!frames.is_empty()).then(|| format!("sequence {} (frames {:?})", i, frames))
+            })
+            .collect();
+        (!incomplete.is_empty()).then(|| format!("Required labels are not set in {}.", incomplete.join(", ")))
+    }
+
     /// Get the index to the annotation sequence that is closest to the given point in normalized coordinates. Also returns the distance.
     /// Returns: (index, distance, contains_point)
     pub fn get_closest_annotation_sequence(&self, frame: usize, x: f32, y: f32, must_contain: bool) -> Option<(usize, f32, bool)> {
@@ -222,18 +279,45 @@ impl LabelTask {
     }
 
     /// Add an entirely new annotation object, as opposed to editing an existing one.
+    /// The track labels are taken from `ann`.
     pub fn add_new_annotation_sequence(&mut self, ann: Annotation) {
         let mut anns = AnnotationSequence::new();
         anns.annotations.push(ann);
+        anns.update_track_labels(&self.configs);
         self.sequences.push(anns);
     }
 
+    /// Split the annotation sequence at `index` at `frame` (see `AnnotationSequence::split_at`), e.g. after an ID switch.
+    /// The second part is inserted after it and becomes the current sequence. Returns the index of the second part,
+    /// or None if the sequence has no box at or before `frame`.
+    pub fn split_sequence(&mut self, index: usize, frame: usize) -> Option<usize> {
+        let second = self.sequences.get_mut(index)?.split_at(frame)?;
+        self.sequences.insert(index + 1, second);
+        self.current_sequence = index + 1;
+        Some(index + 1)
+    }
+
+    /// Merge the annotation sequence `other` into the one at `index` (see `AnnotationSequence::merge`) and remove `other`.
+    /// The merged sequence becomes the current one. Returns its new index and the number of keyframes of `other`
+    /// that were dropped because both sequences were visible there, or None if the indices are invalid.
+    pub fn merge_sequences(&mut self, index: usize, other: usize) -> Option<(usize, usize)> {
+        if index == other || index >= self.sequences.len() || other >= self.sequences.len() {
+            return None;
+        }
+        let other_sequence = self.sequences.remove(other);
+        let index = if other < index { index - 1 } else { index };
+        let dropped = self.sequences[index].merge(&other_sequence);
+        self.current_sequence = index;
+        Some((index, dropped))
+    }
 }
 
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Annotation {
     /// The number of elements in `labels` is determined by the LabelConfigs used for this Annotation.
+    /// In a keyframe of an `AnnotationSequence`, the labels with scope 'track' are copies of `AnnotationSequence::labels`,
+    /// which are left out when saving.
     pub labels: Vec<Label>,
     pub bbox: SerializableAabb,
     pub frame: usize,
@@ -242,7 +326,7 @@ pub struct Annotation {
     pub interpolated: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct SerializablePoint<T> {
     pub x: T,
     pub y: T
@@ -255,7 +339,7 @@ impl<T: Copy> SerializablePoint<T> {
 }
 
 /// This is introduced so that we can use automatic Serialize/Deserialize derivation. parry2d's Aabb does not implement that.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct SerializableAabb {
     pub mins: SerializablePoint<f32>,
     pub maxs: SerializablePoint<f32>
@@ -270,26 +354,13 @@ impl Into<Aabb> for &SerializableAabb {
 
 impl Annotation {
     pub fn new(config: &LabelConfigs, start_x: f32, start_y: f32, frame: usize) -> Self {
-        let mut result = Self {
-            labels: vec![],
+        Self {
+            labels: config.label_configs.iter().map(LabelConfig::default_label).collect(),
             bbox: SerializableAabb{ mins: SerializablePoint{x: start_x, y: start_y }, maxs: SerializablePoint { x: start_x, y: start_y } },
             frame,
             invisible: false,
             interpolated: false
-        };
-
-        for c in &config.label_configs {
-            match c {
-                LabelConfig::S(lcs) => {
-                    result.labels.push(Label::S(LabelInstance { name: lcs.name.clone(), state: lcs.states[0].clone() }));
-                },
-                LabelConfig::I(lci) => {
-                    result.labels.push(Label::I(LabelInstance { name: lci.name.clone(), state: lci.first }));
-                }
-            }
         }
-
-        result
     }
 
     /// Returns the distance and whether the point is inside the box.
@@ -330,17 +401,89 @@ impl Default for Annotation {
 }
 
 /// Sequence of annotations, i.e. a sequence of boxes that are interpolated between.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct AnnotationSequence {
-    pub annotations: Vec<Annotation>
+    pub annotations: Vec<Annotation>,
+    /// The labels with scope 'track', which are the same in every frame of the sequence.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<Label>,
 }
 
 impl AnnotationSequence {
     pub fn new() -> Self {
-        Self { annotations: Vec::<Annotation>::new() }
+        Self { annotations: Vec::<Annotation>::new(), labels: Vec::new() }
+    }
+
+    /// Make `labels` hold exactly the labels with scope 'track' in `configs`, e.g. after loading annotations or a config.
+    /// The labels stored in the sequence are first written back into every keyframe (at their position in `configs`,
+    /// since saved keyframes do not contain them), so a label that is no longer track-scoped keeps its state.
+    /// A label that becomes track-scoped takes its state from the first keyframe.
+    pub fn update_track_labels(&mut self, configs: &LabelConfigs) {
+        let stored = std::mem::take(&mut self.labels);
+        for a in &mut self.annotations {
+            for (i, c) in configs.label_configs.iter().enumerate() {
+                if let Some(l) = stored.iter().find(|l| l.name() == c.name()) {
+                    match a.labels.iter_mut().find(|al| al.name() == c.name()) {
+                        Some(al) => *al = l.clone(),
+                        None => a.labels.insert(i.min(a.labels.len()), l.clone()),
+                    }
+                }
+            }
+        }
+
+        let first = self.annotations.first().map(|a| a.labels.as_slice()).unwrap_or_default();
+        self.labels = configs.track_labels().into_iter()
+            .map(|default| {
+                first.iter().chain(&stored).find(|l| l.name() == default.name()).cloned().unwrap_or(default)
+            })
+            .collect();
+    }
+
+    /// Remove the labels with scope 'track' from the annotations, so they are only stored once in `labels`, e.g. for saving.
+    /// `update_track_labels` adds them back.
+    fn strip_track_labels(&mut self) {
+        for a in &mut self.annotations {
+            a.labels.retain(|l| !self.labels.iter().any(|t| t.name() == l.name()));
+        }
+    }
+
+    /// Overwrite the labels with scope 'track' in `labels` with the ones of this sequence.
+    pub fn apply_track_labels(&self, labels: &mut [Label]) {
+        apply_labels(&self.labels, labels);
+    }
+
+    /// Take the states of the labels with scope 'track' from `labels`, which changes them in every frame.
+    pub fn set_track_labels(&mut self, labels: &[Label]) {
+        apply_labels(labels, &mut self.labels);
+        for a in &mut self.annotations {
+            apply_labels(&self.labels, &mut a.labels);
+        }
+    }
+
+    /// Edit the labels and the invisible flag of the annotation at `frame`. Labels with scope 'track' are set for
+    /// the whole sequence (see `set_track_labels`), a keyframe is only added (see `edit_annotation`) if anything else changed.
+    pub fn edit_labels(&mut self, frame: usize, annotation: &Annotation) {
+        self.set_track_labels(&annotation.labels);
+        if self.get_interpolated_annotation_for_frame(frame).as_ref() != Some(annotation) {
+            self.edit_annotation(frame, annotation);
+        }
+    }
+
+    /// Frames of the visible keyframes in which labels that are required by `configs` are not set.
+    /// The sequence counts as complete if there are none.
+    pub fn incomplete_frames(&self, configs: &LabelConfigs) -> Vec<usize> {
+        self.annotations.iter()
+            .filter(|a| {
+                let mut labels = a.labels.clone();
+                self.apply_track_labels(&mut labels);
+                !a.invisible && !configs.missing_labels(&labels).is_empty()
+            })
+            .map(|a| a.frame)
+            .collect()
     }
 
     /// Propagate the labels (not the rectangles) from the given frame to all following key frames.
+    /// This is only needed for labels with scope 'frame', the others are the same in every frame anyway.
     pub fn propagate(&mut self, frame: usize) {
         let ann = self.get_interpolated_annotation_for_frame(frame);
         if let Some(ann) = ann {
@@ -357,7 +500,14 @@ impl AnnotationSequence {
         }
     }
 
+    /// The labels with scope 'track' are the ones of the sequence.
     pub fn get_interpolated_annotation_for_frame(&self, frame: usize) -> Option<Annotation> {
+        let mut a = self.interpolate_annotation(frame)?;
+        self.apply_track_labels(&mut a.labels);
+        Some(a)
+    }
+
+    fn interpolate_annotation(&self, frame: usize) -> Option<Annotation> {
         let anns = self.get_annotations_for_frame(frame);
         if let Some((index0, index1_)) = anns {
             let frame0 = self.annotations[index0].frame;
@@ -393,6 +543,13 @@ impl AnnotationSequence {
                     a.bbox.mins.y = mins.y;
                     a.bbox.maxs.x = maxs.x;
                     a.bbox.maxs.y = maxs.y;
+
+                    // Float labels change linearly between keyframes like the box, other labels keep their state.
+                    for (l, l1) in a.labels.iter_mut().zip(&self.annotations[index1].labels) {
+                        if let (Label::F(LabelInstance { state: Some(v), .. }), Label::F(LabelInstance { state: Some(v1), .. })) = (l, l1) {
+                            *v = (1.0 - t) * *v + t * v1;
+                        }
+                    }
                     return Some(a);
                 } else {
                     // This means we can just return the first annotation, since we extrapolate the same one, if it's not out of frame.
@@ -437,6 +594,147 @@ impl AnnotationSequence {
         None
     }
 
+    /// Remove the keyframe at `frame`. Returns false if there is no keyframe at `frame`.
+    pub fn delete_keyframe(&mut self, frame: usize) -> bool {
+        let len = self.annotations.len();
+        self.annotations.retain(|a| a.frame != frame);
+        self.annotations.len() != len
+    }
+
+    /// Turn the interpolated annotation at `frame` into a keyframe, so it stays the same when other keyframes are deleted.
+    fn make_keyframe(&mut self, frame: usize) {
+        if let Some(a) = self.get_interpolated_annotation_for_frame(frame) {
+            if a.interpolated {
+                self.edit_annotation(frame, &a);
+            }
+        }
+    }
+
+    /// Remove all keyframes after `frame`, so the track ends there. The annotation at `frame` is kept as a keyframe,
+    /// so the sequence does not change up to `frame`, and followed by an invisible keyframe at `frame + 1`.
+    /// Returns the number of removed keyframes.
+    pub fn delete_keyframes_after(&mut self, frame: usize) -> usize {
+        self.make_keyframe(frame);
+        let len = self.annotations.len();
+        self.annotations.retain(|a| a.frame <= frame);
+        let removed = len - self.annotations.len();
+        if let Some(last) = self.annotations.last().filter(|a| a.frame == frame && !a.invisible) {
+            let mut end = last.clone();
+            end.frame = frame + 1;
+            end.invisible = true;
+            self.annotations.push(end);
+        }
+        removed
+    }
+
+    /// Remove all keyframes before `frame`. The annotation at `frame` is kept as a keyframe,
+    /// so the sequence does not change from `frame` on. Returns the number of removed keyframes.
+    pub fn delete_keyframes_before(&mut self, frame: usize) -> usize {
+        self.make_keyframe(frame);
+        let len = self.annotations.len();
+        self.annotations.retain(|a| a.frame >= frame);
+        len - self.annotations.len()
+    }
+
+    /// Remove all keyframes from `first` to `last` (inclusive), the boxes in between are interpolated
+    /// from the keyframes around the range. An invisible last keyframe is kept, since it marks the end of the track.
+    /// Returns the number of removed keyframes.
+    pub fn clear_frames(&mut self, first: usize, last: usize) -> usize {
+        let end = self.annotations.last().filter(|a| a.invisible).map(|a| a.frame);
+        let len = self.annotations.len();
+        self.annotations.retain(|a| a.frame < first || a.frame > last || Some(a.frame) == end);
+        len - self.annotations.len()
+    }
+
+    /// Split the sequence at `frame`. Returns the part from `frame` on as a new sequence, which starts with
+    /// the box at `frame`. This sequence keeps the part before `frame` unchanged and ends there with an invisible keyframe.
+    /// Returns None if the sequence does not start before `frame`, since there would be nothing left.
+    pub fn split_at(&mut self, frame: usize) -> Option<AnnotationSequence> {
+        if frame == 0 || self.annotations.first().is_none_or(|a| a.frame >= frame) {
+            return None;
+        }
+        self.make_keyframe(frame - 1);
+        self.make_keyframe(frame);
+
+        let index = self.annotations.iter().position(|a| a.frame >= frame)?;
+        let second = AnnotationSequence { annotations: self.annotations.split_off(index), labels: self.labels.clone() };
+        let mut end = second.annotations[0].clone();
+        end.invisible = true;
+        self.annotations.push(end);
+        Some(second)
+    }
+
+    /// Merge the keyframes of `other` into this sequence, e.g. when one object was started as two tracks.
+    /// Where both sequences are visible, this sequence is kept and the keyframes of `other` are dropped.
+    /// The labels with scope 'track' of this sequence are kept.
+    /// Everywhere else the box of whichever sequence is visible is used, so neither sequence changes
+    /// where it is the only one that is visible. Returns the number of dropped keyframes of `other`.
+    pub fn merge(&mut self, other: &AnnotationSequence) -> usize {
+        let visible = |s: &AnnotationSequence, frame: usize| s.get_interpolated_annotation_for_frame(frame).filter(|a| !a.invisible);
+        let is_keyframe = |s: &AnnotationSequence, frame: usize| s.annotations.iter().any(|a| a.frame == frame);
+
+        let mut frames: Vec<usize> = self.annotations.iter().chain(&other.annotations).map(|a| a.frame).collect();
+        frames.sort();
+        frames.dedup();
+
+        let mut merged: Vec<Annotation> = vec![];
+        let mut dropped = 0;
+        // Whether the previous keyframe of the merged sequence came from `other`.
+        let mut previous_source: Option<bool> = None;
+        for frame in frames {
+            let (annotation, from_other) = match (visible(self, frame), visible(other, frame)) {
+                (Some(a), b) => {
+                    if b.is_some() && is_keyframe(other, frame) {
+                        dropped += 1;
+                    }
+                    (a, false)
+                },
+                (None, Some(b)) => (b, true),
+                // Neither is visible, keep the invisible box of whichever sequence has one.
+                (None, None) => match self.get_interpolated_annotation_for_frame(frame) {
+                    Some(a) => (a, false),
+                    None => (other.get_interpolated_annotation_for_frame(frame).unwrap(), true),
+                },
+            };
+
+            let source = if from_other { other } else { &*self };
+            let switched = previous_source.is_some_and(|p| p != from_other);
+            if !switched && !is_keyframe(source, frame) {
+                continue;
+            }
+            if switched {
+                // Keep the previous sequence unchanged up to the frame before the switch.
+                let previous = if from_other { &*self } else { other };
+                if let Some(last) = merged.last() {
+                    if last.frame + 1 < frame {
+                        if let Some(a) = previous.get_interpolated_annotation_for_frame(frame - 1) {
+                            merged.push(a);
+                        }
+                    }
+                }
+            }
+            merged.push(annotation);
+            previous_source = Some(from_other);
+        }
+
+        for a in &mut merged {
+            a.interpolated = false;
+        }
+        self.annotations = merged;
+        dropped
+    }
+
+    /// Move the keyframe at frame `from` to frame `to`. The keyframe can not be moved past its neighbours,
+    /// so `to` is clamped between them. Returns the new frame of the keyframe, or None if there is no keyframe at `from`.
+    pub fn move_keyframe(&mut self, from: usize, to: usize) -> Option<usize> {
+        let index = self.annotations.iter().position(|a| a.frame == from)?;
+        let min = if index > 0 { self.annotations[index - 1].frame + 1 } else { 0 };
+        let max = self.annotations.get(index + 1).map_or(usize::MAX, |a| a.frame - 1);
+        let to = to.clamp(min, max);
+        self.annotations[index].frame = to;
+        Some(to)
+    }
+
         /// Either edits the annotation if there already is one in the current AnnotationSequence in this frame,
     /// or adds a new keyframe annotation to this annotationsequence otherwise.
     pub fn edit_annotation(&mut self, frame: usize, annotation: &Annotation) {
@@ -468,6 +766,16 @@ impl AnnotationSequence {
 }
 
 
+/// Overwrite the labels in `labels` that have the same name as one in `from`.
+fn apply_labels(from: &[Label], labels: &mut [Label]) {
+    for l in labels {
+        if let Some(f) = from.iter().find(|f| f.name() == l.name()) {
+            *l = f.clone();
+        }
+    }
+}
+
+
 // Types for annotation editing:
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum BoxCorner {LU, RU, RD, LD}
@@ -517,4 +825,205 @@ impl Default for AnnotationAction {
     fn default() -> Self {
         AnnotationAction::new()
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Temporary directory for test output, removed again when dropped.
+    pub(crate) struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("labelo_test_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        pub fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Adds a sequence with a single keyframe showing an animal, returns the keyframe for editing.
+    pub(crate) fn add_box(task: &mut LabelTask, mins: (f32, f32), maxs: (f32, f32), frame: usize, animal: &str) -> Annotation {
+        let mut a = Annotation::new(&task.configs, mins.0, mins.1, frame);
+        a.bbox.maxs = SerializablePoint::new(maxs.0, maxs.1);
+        a.labels[0] = Label::S(LabelInstance::new("animal", animal.to_string()));
+        task.add_new_annotation_sequence(a.clone());
+        a
+    }
+
+    fn sequence_with_keyframes(frames: &[usize]) -> AnnotationSequence {
+        let configs = LabelConfigs::default();
+        let mut s = AnnotationSequence::new();
+        for &f in frames {
+            let mut a = Annotation::new(&configs, 0.0, 0.0, f);
+            a.bbox.maxs = SerializablePoint::new(0.1 + f as f32 / 100.0, 0.1);
+            s.annotations.push(a);
+        }
+        s
+    }
+
+    fn keyframes(s: &AnnotationSequence) -> Vec<usize> {
+        s.annotations.iter().map(|a| a.frame).collect()
+    }
+
+    #[test]
+    fn delete_keyframes() {
+        let mut s = sequence_with_keyframes(&[0, 10, 20, 30]);
+        assert!(s.delete_keyframe(10));
+        assert!(!s.delete_keyframe(11));
+        assert_eq!(keyframes(&s), vec![0, 20, 30]);
+
+        // The box at the current frame is kept, interpolation up to it does not change.
+        let before = s.get_interpolated_annotation_for_frame(25).unwrap().bbox;
+        assert_eq!(s.delete_keyframes_after(25), 1);
+        assert_eq!(keyframes(&s), vec![0, 20, 25, 26]);
+        assert!(s.get_interpolated_annotation_for_frame(25).unwrap().bbox == before);
+        // The track ends after `frame`.
+        assert!(s.get_interpolated_annotation_for_frame(26).unwrap().invisible);
+        assert!(s.get_interpolated_annotation_for_frame(100).unwrap().invisible);
+
+        assert_eq!(s.delete_keyframes_before(5), 1);
+        assert_eq!(keyframes(&s), vec![5, 20, 25, 26]);
+        assert!(s.get_interpolated_annotation_for_frame(4).is_none());
+
+        // The invisible keyframe at the end is kept.
+        assert_eq!(s.clear_frames(6, 30), 2);
+        assert_eq!(keyframes(&s), vec![5, 26]);
+        assert!(!s.get_interpolated_annotation_for_frame(25).unwrap().invisible);
+        assert!(s.get_interpolated_annotation_for_frame(26).unwrap().invisible);
+        assert_eq!(s.clear_frames(6, 30), 0);
+
+        assert_eq!(s.move_keyframe(5, 7), Some(7));
+        let mut s = sequence_with_keyframes(&[0, 10, 20]);
+        assert_eq!(s.move_keyframe(10, 30), Some(19));
+        assert_eq!(s.move_keyframe(11, 12), None);
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let original = sequence_with_keyframes(&[0, 10, 20]);
+        // Box widths in 1/1000, to compare interpolated boxes.
+        let boxes = |s: &AnnotationSequence| (0..30).map(|f| s.get_interpolated_annotation_for_frame(f)
+            .filter(|a| !a.invisible).map(|a| (a.bbox.maxs.x * 1000.0).round() as i32)).collect::<Vec<_>>();
+
+        let mut first = original.clone();
+        assert!(first.split_at(0).is_none());
+        let second = first.split_at(15).unwrap();
+        assert_eq!(keyframes(&first), vec![0, 10, 14, 15]);
+        assert!(first.annotations[3].invisible);
+        assert_eq!(keyframes(&second), vec![15, 20]);
+        // Together, the parts have the same boxes as the original.
+        let (a, b, o) = (boxes(&first), boxes(&second), boxes(&original));
+        for f in 0..30 {
+            assert_eq!(a[f].or(b[f]), o[f]);
+            assert!(a[f].is_none() || b[f].is_none());
+        }
+
+        // Merging the parts restores the boxes, in either order.
+        let mut merged = first.clone();
+        assert_eq!(merged.merge(&second), 0);
+        assert_eq!(boxes(&merged), o);
+        let mut merged = second.clone();
+        assert_eq!(merged.merge(&first), 0);
+        assert_eq!(boxes(&merged), o);
+
+        // Where both are visible, the sequence that is merged into wins.
+        let mut task = LabelTask::new();
+        task.sequences = vec![sequence_with_keyframes(&[0, 10]), sequence_with_keyframes(&[5, 20])];
+        assert_eq!(task.merge_sequences(1, 0), Some((0, 1)));
+        assert_eq!(task.sequences.len(), 1);
+        assert_eq!(keyframes(&task.sequences[0]), vec![0, 4, 5, 20]);
+        assert_eq!(task.split_sequence(0, 10), Some(1));
+        assert_eq!(task.current_sequence, 1);
+    }
+
+    #[test]
+    fn interpolate_float_labels() {
+        let mut s = AnnotationSequence::new();
+        for (frame, distance, name) in [(0, 2.0, "Bob"), (10, 4.0, "Rob")] {
+            s.annotations.push(Annotation { frame, labels: vec![
+                Label::F(LabelInstance::new("distance", distance)),
+                Label::T(LabelInstance::new("name", name.to_string()))], ..Default::default() });
+        }
+
+        let a = s.get_interpolated_annotation_for_frame(5).unwrap();
+        assert!(a.labels[0] == Label::F(LabelInstance::new("distance", 3.0)));
+        assert!(a.labels[1] == Label::T(LabelInstance::new("name", "Bob".to_string())));
+
+        s.propagate(5);
+        assert!(s.annotations[1].labels[0] == Label::F(LabelInstance::new("distance", 3.0)));
+        assert_eq!(s.annotations[1].labels[1].state_string(), "Bob");
+    }
+
+    #[test]
+    fn track_labels() {
+        let mut task = LabelTask::new();
+        let mut a = add_box(&mut task, (0.1, 0.1), (0.1, 0.1), 0, "cat");
+        a.labels[0] = Label::S(LabelInstance::new("animal", "dog".to_string()));
+        task.sequences[0].edit_annotation(10, &a);
+
+        // The animal becomes track-scoped and is taken from the first keyframe.
+        if let LabelConfig::S(lcs) = &mut task.configs.label_configs[0] {
+            lcs.scope = LabelScope::Track;
+        }
+        task.update_track_labels();
+        let s = &mut task.sequences[0];
+        assert_eq!(s.labels.len(), 1);
+        assert_eq!(s.get_interpolated_annotation_for_frame(10).unwrap().labels[0].state_string(), "cat");
+
+        // Changing it in one frame changes it everywhere, without adding a keyframe.
+        let mut b = s.get_interpolated_annotation_for_frame(5).unwrap();
+        b.labels[0] = Label::S(LabelInstance::new("animal", "possum".to_string()));
+        s.edit_labels(5, &b);
+        assert_eq!(keyframes(s), vec![0, 10]);
+        assert_eq!(s.get_interpolated_annotation_for_frame(0).unwrap().labels[0].state_string(), "possum");
+        assert!(task.incomplete_report(None).is_none());
+
+        // Frame-scoped labels still need a keyframe.
+        let s = &mut task.sequences[0];
+        b.labels[1] = Label::I(LabelInstance::new("size", 3));
+        s.edit_labels(5, &b);
+        assert_eq!(keyframes(s), vec![0, 5, 10]);
+        assert_eq!(s.get_interpolated_annotation_for_frame(0).unwrap().labels[1].state_string(), "");
+
+        // Every keyframe holds the same copy as the sequence.
+        assert!(task.sequences[0].annotations.iter().all(|a| a.labels[0] == task.sequences[0].labels[0]));
+
+        // Track labels are only saved once per sequence, and restored into the keyframes when loading.
+        let tmp = TempDir::new("track_labels");
+        let path = tmp.join("annotations.json");
+        task.save_annotations(&path, 11, true).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(json[0]["labels"][0]["S"]["state"], "possum");
+        assert!(json[0]["annotations"].as_array().unwrap().iter().all(|a| a["labels"].as_array().unwrap().len() == 1));
+        let mut loaded = LabelTask::new();
+        loaded.configs = task.configs.clone();
+        loaded.load_annotations(&path, true).unwrap();
+        assert!(loaded.sequences == task.sequences);
+        // With a config in which the animal is per frame, it is written into every keyframe.
+        let mut loaded = LabelTask::new();
+        loaded.load_annotations(&path, true).unwrap();
+        assert!(loaded.sequences[0].labels.is_empty());
+        assert!(loaded.sequences[0].annotations.iter().all(|a| a.labels.len() == 2 && a.labels[0].state_string() == "possum"));
+
+        // Back to frame scope, every keyframe keeps the state.
+        if let LabelConfig::S(lcs) = &mut task.configs.label_configs[0] {
+            lcs.scope = LabelScope::Frame;
+        }
+        task.update_track_labels();
+        assert!(task.sequences[0].labels.is_empty());
+        assert!(task.sequences[0].annotations.iter().all(|a| a.labels[0].state_string() == "possum"));
+    }
+}
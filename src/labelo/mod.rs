@@ -1,5 +1,19 @@
 pub mod config;
 pub mod labelo;
 pub mod image_provider;
+pub mod coco;
+pub mod yolo;
+pub mod mot;
+pub mod cvat;
+pub mod voc;
+pub mod csv;
+pub mod validate;
+pub mod stats;
+pub mod history;
+pub mod view;
+pub mod keybindings;
+pub mod playback;
+#[cfg(feature = "video")]
+pub mod video;
 use labelo::*;
 use config::*;
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+impl LabelTask {
+    /// Export the annotations as MOTChallenge ground truth (`gt.txt`), with lines `frame,id,x,y,w,h,conf,class,visibility`.
+    /// Frames and track ids are 1-based as in MOTChallenge, the track id is the index of the annotation sequence plus one.
+    /// Boxes are in pixels. Visible boxes get visibility 1, invisible keyframes are written with visibility 0
+    /// so that the end of a track survives a round trip. The class is the 1-based index of the state of the
+    /// string label named `class_label` (or the first string label), or -1 if there is no string label.
+    /// With `class_depth`, the taxonomy of the class label is collapsed to that many levels.
+    pub fn export_mot<P: ImageProvider + ?Sized>(&self, path: &PathBuf, images: &P, class_label: Option<&str>, class_depth: Option<usize>) -> Result<(), String> {
+        let class = self.configs.class_label(class_label, class_depth).ok();
+        let mut lines = String::new();
+
+        for frame in 0..images.frame_count() {
+            let anns = self.get_all_interpolated_annotations_for_frame(frame);
+            if anns.is_empty() {
+                continue;
+            }
+            let [width, height] = images.frame_size(frame)?;
+
+            for (i, a) in anns {
+                if a.invisible && a.interpolated {
+                    continue;
+                }
+
+                let class_id = match &class {
+                    Some((class_index, class_config)) => match a.labels.get(*class_index) {
+                        Some(Label::S(l)) => l.state.as_deref().and_then(|s| class_config.class_index(s)).map(|c| c as i64 + 1).unwrap_or(-1),
+                        _ => -1,
+                    },
+                    None => -1,
+                };
+
+                let x = a.bbox.mins.x * width as f32;
+                let y = a.bbox.mins.y * height as f32;
+                let w = (a.bbox.maxs.x - a.bbox.mins.x) * width as f32;
+                let h = (a.bbox.maxs.y - a.bbox.mins.y) * height as f32;
+                let visibility = if a.invisible { 0.0 } else { 1.0 };
+
+                lines += &format!("{},{},{:.2},{:.2},{:.2},{:.2},1,{},{}\n",
+                    frame + 1, i + 1, x, y, w, h, class_id, visibility);
+            }
+        }
+
+        fs::write(path, lines).map_err(|e| e.to_string())
+    }
+
+    /// Import MOTChallenge ground truth (`frame,id,x,y,w,h[,conf,class,visibility]`), replacing all annotation sequences.
+    /// Every track becomes an annotation sequence (ordered by track id) and every line a keyframe.
+    /// Lines with visibility 0 become invisible keyframes, lines with conf 0 are ignored as in MOTChallenge,
+    /// and two lines for the same track and frame are an error. Known classes set the state of the string label
+    /// named `class_label` (or the first string label), all other labels get their default values.
+    pub fn import_mot<P: ImageProvider + ?Sized>(&mut self, path: &PathBuf, images: &P, class_label: Option<&str>) -> Result<(), String> {
+        let s = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let class = self.configs.class_label(class_label, None).ok();
+        let class_names = class.as_ref().map(|(_, lcs)| lcs.paths()).unwrap_or_default();
+
+        let mut tracks = BTreeMap::<i64, BTreeMap<usize, Annotation>>::new();
+        let mut frame_sizes = BTreeMap::<usize, [usize; 2]>::new();
+
+        for (line_index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parse_error = || format!("Line {}: could not parse '{}'.", line_index + 1, line);
+            let values: Vec<f32> = line.split(',')
+                .map(|v| v.trim().parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| parse_error())?;
+            if values.len() < 6 || values[0] < 1.0 {
+                return Err(parse_error());
+            }
+            if values.get(6).is_some_and(|conf| *conf == 0.0) {
+                continue;
+            }
+
+            let frame = values[0] as usize - 1;
+            let id = values[1] as i64;
+            let [width, height] = match frame_sizes.get(&frame) {
+                Some(size) => *size,
+                None => {
+                    let size = images.frame_size(frame)?;
+                    frame_sizes.insert(frame, size);
+                    size
+                },
+            };
+
+            let mut a = Annotation::new(&self.configs, values[2] / width as f32, values[3] / height as f32, frame);
+            a.bbox.maxs = SerializablePoint::new((values[2] + values[4]) / width as f32, (values[3] + values[5]) / height as f32);
+            a.invisible = values.get(8).is_some_and(|v| *v <= 0.0);
+
+            if let (Some((class_index, _)), Some(class_id)) = (&class, values.get(7)) {
+                let state = (*class_id as i64 - 1).try_into().ok().and_then(|c: usize| class_names.get(c));
+                if let (Some(state), Some(Label::S(l))) = (state, a.labels.get_mut(*class_index)) {
+                    l.state = Some(state.clone());
+                }
+            }
+
+            if tracks.entry(id).or_default().insert(frame, a).is_some() {
+                return Err(format!("Line {}: track {} has more than one box in frame {}.", line_index + 1, id, frame + 1));
+            }
+        }
+
+        self.sequences.clear();
+        for (_id, annotations) in tracks {
+            self.sequences.push(AnnotationSequence { annotations: annotations.into_values().collect(), labels: vec![] });
+        }
+        self.update_track_labels();
+        self.current_sequence = 0;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn mot_round_trip() {
+        let mut task = LabelTask::new();
+        let mut a = add_box(&mut task, (0.1, 0.2), (0.3, 0.6), 1, "dog");
+        a.frame = 3;
+        a.invisible = true;
+        task.sequences[0].edit_annotation(3, &a);
+
+        let images = TestImages { count: 5, size: [100, 50] };
+        let tmp = TempDir::new("mot_round_trip");
+        let path = tmp.join("gt.txt");
+        task.export_mot(&path, &images, None, None).unwrap();
+
+        let s = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "2,1,10.00,10.00,20.00,20.00,1,2,1");
+        assert_eq!(lines[2], "4,1,10.00,10.00,20.00,20.00,1,2,0");
+
+        let mut imported = LabelTask::new();
+        imported.import_mot(&path, &images, None).unwrap();
+        assert_eq!(imported.sequences.len(), 1);
+        let anns = &imported.sequences[0].annotations;
+        assert_eq!(anns.len(), 3);
+        assert_eq!(anns[0].frame, 1);
+        assert!(anns[2].invisible);
+        assert!((anns[0].bbox.maxs.y - 0.6).abs() < 1e-6);
+        match &anns[0].labels[0] {
+            Label::S(l) => assert_eq!(l.state.as_deref(), Some("dog")),
+            _ => panic!("Expected a string label."),
+        }
+
+        // Lines with conf 0 are ignored, a second box of a track in the same frame is an error.
+        fs::write(&path, format!("{}2,2,10,10,20,20,0,1,1\n", s)).unwrap();
+        imported.import_mot(&path, &images, None).unwrap();
+        assert_eq!(imported.sequences.len(), 1);
+        fs::write(&path, format!("{}4,1,10,10,20,20,1,2,1\n", s)).unwrap();
+        assert_eq!(imported.import_mot(&path, &images, None).unwrap_err(), "Line 4: track 1 has more than one box in frame 4.");
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+/// Speed factors that can be selected for playback.
+pub const PLAYBACK_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Time-based playback of the frames at `fps` frames per second times `speed`.
+/// The frame to show is computed from the time since playback started, so frames are dropped
+/// when loading them takes longer than the time per frame.
+pub struct Playback {
+    pub playing: bool,
+    pub fps: usize,
+    pub speed: f32,
+    pub reverse: bool,
+    /// Start over at the other end instead of stopping at the last (or first) frame.
+    pub looping: bool,
+    /// Number of frames that were skipped since playback started.
+    pub dropped_frames: usize,
+
+    /// Time and frame from which the current frame is computed, with the fps, speed and direction at that time.
+    anchor: Option<(Instant, usize, usize, f32, bool)>,
+    /// The frame returned by the last call to `advance`.
+    last_frame: usize,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            fps: 30,
+            speed: 1.0,
+            reverse: false,
+            looping: false,
+            dropped_frames: 0,
+            anchor: None,
+            last_frame: 0,
+        }
+    }
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn toggle(&mut self) {
+        self.playing = !self.playing;
+        self.anchor = None;
+        self.dropped_frames = 0;
+    }
+
+    /// Frames per second, including the speed factor.
+    fn rate(&self) -> f64 {
+        self.fps.max(1) as f64 * self.speed as f64
+    }
+
+    /// The frame to show at `now`, with `current` being the frame that is shown now.
+    /// Playback starts over from `current` if the frame was changed elsewhere, e.g. with the slider,
+    /// or if the fps, speed or direction changed. At the end, playback stops, or loops to the other end.
+    pub fn advance(&mut self, now: Instant, current: usize, frame_count: usize) -> usize {
+        if !self.playing || frame_count == 0 {
+            return current;
+        }
+
+        let settings_changed = self.anchor.is_some_and(|(_, _, fps, speed, reverse)|
+            fps != self.fps || speed != self.speed || reverse != self.reverse);
+        if self.anchor.is_none() || settings_changed || current != self.last_frame {
+            self.anchor = Some((now, current, self.fps, self.speed, self.reverse));
+        }
+        let (start, start_frame, ..) = self.anchor.unwrap();
+
+        let steps = (now.saturating_duration_since(start).as_secs_f64() * self.rate()) as i64;
+        let mut frame = if self.reverse { start_frame as i64 - steps } else { start_frame as i64 + steps };
+        if frame < 0 || frame >= frame_count as i64 {
+            if self.looping {
+                frame = frame.rem_euclid(frame_count as i64);
+            } else {
+                frame = frame.clamp(0, frame_count as i64 - 1);
+                self.playing = false;
+            }
+        }
+
+        let frame = frame as usize;
+        if frame != current {
+            // Frames moved in the playback direction, which can wrap around when looping.
+            let step = if self.reverse { current as i64 - frame as i64 } else { frame as i64 - current as i64 };
+            self.dropped_frames += (step.rem_euclid(frame_count as i64) as usize).saturating_sub(1);
+        }
+        self.last_frame = frame;
+        frame
+    }
+
+    /// Time from `now` until the next frame is due.
+    pub fn time_to_next_frame(&self, now: Instant) -> Duration {
+        match self.anchor {
+            Some((start, ..)) if self.playing => {
+                let elapsed = now.saturating_duration_since(start).as_secs_f64();
+                let next = ((elapsed * self.rate()).floor() + 1.0) / self.rate();
+                Duration::from_secs_f64((next - elapsed).max(0.0))
+            },
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_based_playback() {
+        let t0 = Instant::now();
+        let ms = |ms: u64| t0 + Duration::from_millis(ms);
+        let mut p = Playback::new();
+        p.fps = 10;
+        p.toggle();
+
+        assert_eq!(p.advance(ms(0), 5, 20), 5);
+        assert_eq!(p.advance(ms(99), 5, 20), 5);
+        assert_eq!(p.advance(ms(100), 5, 20), 6);
+        assert!(p.time_to_next_frame(ms(150)) <= Duration::from_millis(50));
+        // Slow frame loading: frames are dropped to keep the rate.
+        assert_eq!(p.advance(ms(400), 6, 20), 9);
+        assert_eq!(p.dropped_frames, 2);
+
+        // Seeking elsewhere restarts from that frame, at double speed backwards.
+        p.speed = 2.0;
+        p.reverse = true;
+        assert_eq!(p.advance(ms(400), 2, 20), 2);
+        assert_eq!(p.advance(ms(450), 2, 20), 1);
+        assert_eq!(p.advance(ms(600), 1, 20), 0);
+        assert!(!p.playing);
+
+        p.toggle();
+        p.looping = true;
+        assert_eq!(p.advance(ms(600), 0, 20), 0);
+        assert_eq!(p.advance(ms(650), 0, 20), 19);
+        assert!(p.playing);
+        assert_eq!(p.dropped_frames, 0);
+        // Frames dropped while looping count too, also across the wrap.
+        assert_eq!(p.advance(ms(900), 19, 20), 14);
+        assert_eq!(p.dropped_frames, 4);
+        assert_eq!(p.advance(ms(1700), 14, 20), 18);
+        assert_eq!(p.dropped_frames, 4 + 15);
+    }
+}
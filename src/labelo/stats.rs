@@ -0,0 +1,171 @@
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::config::*;
+use super::labelo::*;
+
+/// Number of bins of `Statistics::box_size_histogram`.
+pub const BOX_SIZE_BINS: usize = 10;
+
+/// Dataset statistics, computed by `LabelTask::statistics`.
+#[derive(Serialize)]
+pub struct Statistics {
+    pub frame_count: usize,
+    pub track_count: usize,
+    /// Number of visible boxes that are keyframes.
+    pub keyframe_count: usize,
+    /// Number of visible boxes that are interpolated between (or extrapolated from) keyframes.
+    pub interpolated_count: usize,
+    /// Number of frames with a visible box, per track.
+    pub track_lengths: Vec<usize>,
+    /// Number of visible boxes per state of the class label.
+    pub boxes_per_class: BTreeMap<String, usize>,
+    /// Histogram of the box size sqrt(width * height) in normalized coordinates,
+    /// with `BOX_SIZE_BINS` bins from 0 to 1. Larger boxes are counted in the last bin.
+    pub box_size_histogram: Vec<usize>,
+    /// Frames without any visible box.
+    pub frames_without_annotations: Vec<usize>,
+}
+
+impl LabelTask {
+    /// Compute statistics over the first `frame_count` frames, with the boxes of every annotation sequence
+    /// interpolated for every frame. Invisible boxes are not counted.
+    /// Boxes are counted per state of the string label named `class_label` (or the first string label).
+    pub fn statistics(&self, frame_count: usize, class_label: Option<&str>) -> Statistics {
+        let class = self.configs.class_label(class_label, None).ok();
+
+        let mut stats = Statistics {
+            frame_count,
+            track_count: self.sequences.len(),
+            keyframe_count: 0,
+            interpolated_count: 0,
+            track_lengths: vec![0; self.sequences.len()],
+            boxes_per_class: BTreeMap::new(),
+            box_size_histogram: vec![0; BOX_SIZE_BINS],
+            frames_without_annotations: vec![],
+        };
+        if let Some((_, lcs)) = &class {
+            for s in lcs.paths() {
+                stats.boxes_per_class.insert(s, 0);
+            }
+        }
+
+        for frame in 0..frame_count {
+            let mut has_annotation = false;
+            for (i, a) in self.get_all_interpolated_annotations_for_frame(frame) {
+                if a.invisible {
+                    continue;
+                }
+                has_annotation = true;
+
+                if a.interpolated {
+                    stats.interpolated_count += 1;
+                } else {
+                    stats.keyframe_count += 1;
+                }
+                stats.track_lengths[i] += 1;
+
+                if let Some((class_index, _)) = class {
+                    if let Some(Label::S(l)) = a.labels.get(class_index) {
+                        *stats.boxes_per_class.entry(l.state.clone().unwrap_or("(not set)".to_string())).or_default() += 1;
+                    }
+                }
+
+                let w = (a.bbox.maxs.x - a.bbox.mins.x).max(0.0);
+                let h = (a.bbox.maxs.y - a.bbox.mins.y).max(0.0);
+                let bin = ((w * h).sqrt() * BOX_SIZE_BINS as f32) as usize;
+                stats.box_size_histogram[bin.min(BOX_SIZE_BINS - 1)] += 1;
+            }
+
+            if !has_annotation {
+                stats.frames_without_annotations.push(frame);
+            }
+        }
+
+        stats
+    }
+}
+
+/// Format sorted frame numbers as ranges, e.g. "0-3, 7, 9-10".
+fn frame_ranges(frames: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &f in frames {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == f => *end = f,
+            _ => ranges.push((f, f)),
+        }
+    }
+    ranges.iter()
+        .map(|(start, end)| if start == end { format!("{}", start) } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<28} {:>8}", "Frames", self.frame_count)?;
+        writeln!(f, "{:<28} {:>8}", "Tracks", self.track_count)?;
+        writeln!(f, "{:<28} {:>8}", "Boxes", self.keyframe_count + self.interpolated_count)?;
+        writeln!(f, "{:<28} {:>8}", "  keyframes", self.keyframe_count)?;
+        writeln!(f, "{:<28} {:>8}", "  interpolated", self.interpolated_count)?;
+        writeln!(f, "{:<28} {:>8}", "Frames without annotations", self.frames_without_annotations.len())?;
+
+        if !self.track_lengths.is_empty() {
+            let min = self.track_lengths.iter().min().unwrap();
+            let max = self.track_lengths.iter().max().unwrap();
+            let mean = self.track_lengths.iter().sum::<usize>() as f32 / self.track_lengths.len() as f32;
+            writeln!(f, "\nTrack length (frames)        min {}, mean {:.1}, max {}", min, mean, max)?;
+            for (i, l) in self.track_lengths.iter().enumerate() {
+                writeln!(f, "  track {:<20} {:>8}", i, l)?;
+            }
+        }
+
+        if !self.boxes_per_class.is_empty() {
+            writeln!(f, "\nBoxes per class")?;
+            for (class, count) in &self.boxes_per_class {
+                writeln!(f, "  {:<26} {:>8}", class, count)?;
+            }
+        }
+
+        writeln!(f, "\nBox size sqrt(w*h), normalized")?;
+        let max_count = self.box_size_histogram.iter().max().copied().unwrap_or(0).max(1);
+        for (i, count) in self.box_size_histogram.iter().enumerate() {
+            let bar = "#".repeat(count * 40 / max_count);
+            let line = format!("  {:.1}-{:.1} {:>19} {}", i as f32 / BOX_SIZE_BINS as f32, (i + 1) as f32 / BOX_SIZE_BINS as f32, count, bar);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        if !self.frames_without_annotations.is_empty() {
+            writeln!(f, "\nFrames without annotations: {}", frame_ranges(&self.frames_without_annotations))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::labelo::tests::add_box;
+
+    #[test]
+    fn statistics_densify_sequences() {
+        let mut task = LabelTask::new();
+        let mut a = add_box(&mut task, (0.0, 0.0), (0.25, 0.25), 2, "cat");
+        a.invisible = true;
+        task.sequences[0].edit_annotation(5, &a);
+
+        let stats = task.statistics(8, None);
+        assert_eq!(stats.track_count, 1);
+        assert_eq!(stats.keyframe_count, 1);
+        assert_eq!(stats.interpolated_count, 2);
+        assert_eq!(stats.track_lengths, vec![3]);
+        assert_eq!(stats.boxes_per_class["cat"], 3);
+        assert_eq!(stats.boxes_per_class["dog"], 0);
+        assert_eq!(stats.box_size_histogram[2], 3);
+        assert_eq!(stats.frames_without_annotations, vec![0, 1, 5, 6, 7]);
+        assert_eq!(frame_ranges(&stats.frames_without_annotations), "0-1, 5-7");
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+
+use super::config::*;
+use super::labelo::*;
+
+/// A problem found in the annotations by `LabelTask::validate`.
+pub struct ValidationIssue {
+    /// Index of the annotation sequence.
+    pub sequence: usize,
+    /// Index of the annotation in the sequence, if the problem is about a single annotation.
+    pub annotation: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.annotation {
+            Some(a) => write!(f, "Sequence {}, annotation {}: {}", self.sequence, a, self.message),
+            None => write!(f, "Sequence {}: {}", self.sequence, self.message),
+        }
+    }
+}
+
+/// Check a label against its configuration. Returns a description of the problem, if any.
+/// Unset labels are fine here, missing required labels are checked separately.
+fn validate_label(config: &LabelConfig, label: &Label) -> Option<String> {
+    match (config, label) {
+        (LabelConfig::S(lcs), Label::S(l)) if l.name == lcs.name => {
+            if let Some(state) = &l.state {
+                if lcs.state_index(state).is_none() {
+                    return Some(format!("'{}' is not a state of '{}'.", state, lcs.name));
+                }
+            }
+        },
+        (LabelConfig::I(lci), Label::I(l)) if l.name == lci.name => {
+            if let Some(state) = l.state {
+                if !(lci.first..=lci.last).contains(&state) {
+                    return Some(format!("{} is outside of {}..={} for '{}'.", state, lci.first, lci.last, lci.name));
+                }
+            }
+        },
+        (LabelConfig::B(lcb), Label::B(l)) if l.name == lcb.name => {},
+        (LabelConfig::F(lcf), Label::F(l)) if l.name == lcf.name => {
+            if let Some(state) = l.state {
+                if !(lcf.min..=lcf.max).contains(&state) {
+                    return Some(format!("{} is outside of {}..={} for '{}'.", state, lcf.min, lcf.max, lcf.name));
+                }
+            }
+        },
+        (LabelConfig::T(lct), Label::T(l)) if l.name == lct.name => {
+            if let Some(state) = &l.state {
+                if let Err(message) = lct.check(state) {
+                    return Some(message);
+                }
+            }
+        },
+        (LabelConfig::M(lcm), Label::M(l)) if l.name == lcm.name => {
+            let states = l.state.as_deref().unwrap_or_default();
+            for (i, state) in states.iter().enumerate() {
+                if !lcm.states.contains(state) {
+                    return Some(format!("'{}' is not a state of '{}'.", state, lcm.name));
+                }
+                if states[..i].contains(state) {
+                    return Some(format!("'{}' is selected more than once for '{}'.", state, lcm.name));
+                }
+            }
+        },
+        (LabelConfig::S(lcs), _) => return Some(format!("Expected string label '{}', found '{}'.", lcs.name, label.name())),
+        (LabelConfig::I(lci), _) => return Some(format!("Expected integer label '{}', found '{}'.", lci.name, label.name())),
+        (LabelConfig::B(lcb), _) => return Some(format!("Expected bool label '{}', found '{}'.", lcb.name, label.name())),
+        (LabelConfig::F(lcf), _) => return Some(format!("Expected float label '{}', found '{}'.", lcf.name, label.name())),
+        (LabelConfig::T(lct), _) => return Some(format!("Expected text label '{}', found '{}'.", lct.name, label.name())),
+        (LabelConfig::M(lcm), _) => return Some(format!("Expected multi-select label '{}', found '{}'.", lcm.name, label.name())),
+    }
+    None
+}
+
+impl LabelTask {
+    /// Check all annotations against the label configs and for inconsistent boxes and frames.
+    /// Returns an empty vector if everything is fine.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = vec![];
+
+        for (s, seq) in self.sequences.iter().enumerate() {
+            for (a, ann) in seq.annotations.iter().enumerate() {
+                let mut ann = ann.clone();
+                seq.apply_track_labels(&mut ann.labels);
+                let mut issue = |message: String| {
+                    issues.push(ValidationIssue { sequence: s, annotation: Some(a), message: format!("Frame {}: {}", ann.frame, message) });
+                };
+
+                if ann.labels.len() != self.configs.label_configs.len() {
+                    issue(format!("Found {} labels, but the label config defines {}.", ann.labels.len(), self.configs.label_configs.len()));
+                }
+                for (i, (config, label)) in self.configs.label_configs.iter().zip(&ann.labels).enumerate() {
+                    if let Some(message) = validate_label(config, label) {
+                        issue(message);
+                    } else if !self.configs.is_visible(i, &ann.labels) && *label != config.default_label() {
+                        let condition = config.conditions().visible_if.as_ref().map(|c| c.label.as_str()).unwrap_or_default();
+                        issue(format!("'{}' is set to '{}', but does not apply for this '{}'.", config.name(), label.state_string(), condition));
+                    } else if !ann.invisible && !label.is_set() && self.configs.is_required(i, &ann.labels) {
+                        issue(format!("The required label '{}' is not set.", config.name()));
+                    }
+                }
+
+                let b = &ann.bbox;
+                if b.mins.x > b.maxs.x || b.mins.y > b.maxs.y {
+                    issue("The box is inverted (mins > maxs).".to_string());
+                } else if b.mins.x == b.maxs.x || b.mins.y == b.maxs.y {
+                    issue("The box has zero area.".to_string());
+                }
+                let inside = |v: f32| (0.0..=1.0).contains(&v);
+                if !(inside(b.mins.x) && inside(b.mins.y) && inside(b.maxs.x) && inside(b.maxs.y)) {
+                    issue("The box is not within [0,1].".to_string());
+                }
+
+                if a > 0 {
+                    let previous_frame = seq.annotations[a - 1].frame;
+                    if previous_frame == ann.frame {
+                        issue("Duplicate annotation for this frame.".to_string());
+                    } else if previous_frame > ann.frame {
+                        issue(format!("Frames are not sorted, previous annotation is at frame {}.", previous_frame));
+                    }
+                }
+            }
+
+            if seq.annotations.is_empty() {
+                issues.push(ValidationIssue { sequence: s, annotation: None, message: "The sequence has no annotations.".to_string() });
+            }
+        }
+
+        issues
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_finds_issues() {
+        let mut task = LabelTask::new();
+        let mut a = Annotation::new(&task.configs, 0.1, 0.1, 5);
+        a.bbox.maxs = SerializablePoint::new(0.5, 0.5);
+        task.add_new_annotation_sequence(a.clone());
+        // The animal is required, the size is optional.
+        assert_eq!(task.validate().len(), 1);
+        assert_eq!(task.incomplete_report(None).unwrap(), "Required labels are not set in sequence 0 (frames [5]).");
+        a.labels[0] = Label::S(LabelInstance::new("animal", "cat".to_string()));
+        task.sequences[0].annotations[0] = a.clone();
+        assert!(task.validate().is_empty());
+        assert!(task.incomplete_report(None).is_none());
+
+        // Unknown state, int out of range, box outside of the image, unsorted frames.
+        let mut b = a.clone();
+        b.frame = 2;
+        b.labels[0] = Label::S(LabelInstance::new("animal", "emu".to_string()));
+        b.labels[1] = Label::I(LabelInstance::new("size", 11));
+        b.bbox.maxs.x = 1.5;
+        task.sequences[0].annotations.push(b);
+
+        // Inverted box, labels in the wrong order, duplicate frame.
+        let mut c = a.clone();
+        c.frame = 2;
+        c.bbox.mins.x = 0.6;
+        c.labels.swap(0, 1);
+        task.sequences[0].annotations.push(c);
+
+        task.sequences.push(AnnotationSequence::new());
+
+        let issues: Vec<String> = task.validate().iter().map(|i| i.to_string()).collect();
+        assert_eq!(issues, vec![
+            "Sequence 0, annotation 1: Frame 2: 'emu' is not a state of 'animal'.",
+            "Sequence 0, annotation 1: Frame 2: 11 is outside of 1..=10 for 'size'.",
+            "Sequence 0, annotation 1: Frame 2: The box is not within [0,1].",
+            "Sequence 0, annotation 1: Frame 2: Frames are not sorted, previous annotation is at frame 5.",
+            "Sequence 0, annotation 2: Frame 2: Expected string label 'animal', found 'size'.",
+            "Sequence 0, annotation 2: Frame 2: Expected integer label 'size', found 'animal'.",
+            "Sequence 0, annotation 2: Frame 2: The box is inverted (mins > maxs).",
+            "Sequence 0, annotation 2: Frame 2: Duplicate annotation for this frame.",
+            "Sequence 1: The sequence has no annotations.",
+        ]);
+    }
+
+    #[test]
+    fn validate_conditional_labels() {
+        let mut task = LabelTask::new();
+        task.configs.label_configs.push(LabelConfig::I(LabelConfigInt { name: "antler points".to_string(), first: 0, last: 20, optional: false, scope: LabelScope::Frame,
+            conditions: LabelConditions {
+                visible_if: Some(LabelCondition { label: "animal".to_string(), states: vec!["deer".to_string()] }),
+                required_if: None,
+            } }));
+        task.configs.label_configs[0] = LabelConfig::S(LabelConfigString { name: "animal".to_string(),
+            states: vec!["cat".into(), "deer".into()], optional: false, scope: LabelScope::Frame, conditions: LabelConditions::default() });
+        assert!(task.configs.check().is_ok());
+
+        let mut a = Annotation::new(&task.configs, 0.1, 0.1, 0);
+        a.bbox.maxs = SerializablePoint::new(0.5, 0.5);
+        a.labels[0] = Label::S(LabelInstance::new("animal", "cat".to_string()));
+        assert!(!task.configs.is_visible(2, &a.labels));
+        a.labels[2] = Label::I(LabelInstance::new("antler points", 8));
+        task.add_new_annotation_sequence(a.clone());
+        assert_eq!(task.validate().len(), 1);
+
+        a.labels[0] = Label::S(LabelInstance::new("animal", "deer".to_string()));
+        assert!(task.configs.is_visible(2, &a.labels) && task.configs.is_required(2, &a.labels));
+        task.sequences[0].annotations[0] = a.clone();
+        assert!(task.validate().is_empty());
+
+        // Changing the animal resets the antler points.
+        a.labels[0] = Label::S(LabelInstance::new("animal", "cat".to_string()));
+        task.configs.reset_hidden_labels(&mut a.labels);
+        assert!(!a.labels[2].is_set());
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc::{self, SyncSender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use mini_moka::sync::Cache;
+
+use super::image_provider::*;
+
+/// Frames of a video file, decoded by a locally installed `ffmpeg` (5.1 or newer) that runs as a subprocess.
+/// `ffprobe` reads the timestamps of all frames when the file is opened, so the frame count is exact
+/// and every frame can be seeked to. Decoded frames are cached, and like `ImageDirectory`, the frames
+/// ahead of the requested one are decoded in the background when scrolling in one direction.
+pub struct VideoFile {
+    decoder: VideoDecoder,
+    texture_options: egui::TextureOptions,
+
+    image_cache: Arc<Cache<usize, Arc<egui::ColorImage>>>,
+
+    predictive_frame_sender: Option<SyncSender<usize>>,
+    predictive_loading_thread: Option<JoinHandle<()>>,
+}
+
+/// Everything needed to decode frames, shared with the predictive loading thread.
+#[derive(Clone)]
+struct VideoDecoder {
+    path: path::PathBuf,
+    /// Presentation timestamps of all frames in seconds, relative to the start of the video, sorted.
+    frame_times: Arc<Vec<f64>>,
+    size: [usize; 2],
+}
+
+fn run_ffprobe(args: &[&str], path: &path::Path) -> Result<String, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Could not run ffprobe, is ffmpeg installed? ({})", e))?;
+    if !output.status.success() {
+        return Err(format!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Parse the packet timestamps printed by ffprobe (one `pts_time` per line) into sorted frame times
+/// relative to `start_time`. Returns None if a packet has no timestamp.
+fn parse_frame_times(packets: &str, start_time: f64) -> Option<Vec<f64>> {
+    let mut times = vec![];
+    for line in packets.lines() {
+        let line = line.trim().trim_end_matches(',');
+        if line.is_empty() {
+            continue;
+        }
+        times.push(line.parse::<f64>().ok()? - start_time);
+    }
+    // Packets are in decoding order, which differs from the presentation order with B-frames.
+    times.sort_by(|a, b| a.total_cmp(b));
+    Some(times)
+}
+
+/// Parse a frame rate like "30000/1001".
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    if num > 0.0 && den > 0.0 { Some(num / den) } else { None }
+}
+
+/// Time to seek to before decoding `frame`. ffmpeg drops all frames before the seek time,
+/// so it lies between the previous frame and `frame` to be robust against rounding. None for the first frame.
+fn seek_time(frame_times: &[f64], frame: usize) -> Option<f64> {
+    if frame == 0 || frame >= frame_times.len() {
+        return None;
+    }
+    Some((frame_times[frame - 1] + frame_times[frame]) / 2.0)
+}
+
+impl VideoDecoder {
+    fn open(path: path::PathBuf) -> Result<Self, String> {
+        let info = run_ffprobe(&["-show_entries", "stream=width,height,r_frame_rate:format=start_time", "-of", "json"], &path)?;
+        let info: serde_json::Value = serde_json::from_str(&info).map_err(|e| e.to_string())?;
+        let stream = info["streams"].get(0).ok_or("The file has no video stream.")?;
+        let width = stream["width"].as_u64().ok_or("Could not read the video width.")? as usize;
+        let height = stream["height"].as_u64().ok_or("Could not read the video height.")? as usize;
+        let start_time = info["format"]["start_time"].as_str().and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
+
+        // Reading the packets does not decode the video, so it is fast even for long videos.
+        let packets = run_ffprobe(&["-show_entries", "packet=pts_time", "-of", "csv=p=0"], &path)?;
+        let frame_times = match parse_frame_times(&packets, start_time) {
+            Some(times) => times,
+            None => {
+                // Some containers (e.g. raw streams) have no timestamps, assume a constant frame rate.
+                let fps = stream["r_frame_rate"].as_str().and_then(parse_frame_rate).ok_or("Could not read the frame rate.")?;
+                let count = packets.lines().filter(|l| !l.trim().is_empty()).count();
+                (0..count).map(|i| i as f64 / fps).collect()
+            }
+        };
+        if frame_times.is_empty() {
+            return Err("The video has no frames.".to_string());
+        }
+
+        Ok(VideoDecoder { path, frame_times: Arc::new(frame_times), size: [width, height] })
+    }
+
+    fn frame_count(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    /// Decode up to `count` frames starting at `first` with a single ffmpeg process, calling `f` for each frame.
+    /// Decoding stops early when `f` returns false.
+    fn decode<F: FnMut(usize, egui::ColorImage) -> bool>(&self, first: usize, count: usize, mut f: F) -> Result<(), String> {
+        let count = count.min(self.frame_count().saturating_sub(first));
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut command = Command::new("ffmpeg");
+        // Rotation metadata is ignored, so the frames have the size reported by ffprobe.
+        command.args(["-v", "error", "-nostdin", "-noautorotate"]);
+        if let Some(t) = seek_time(&self.frame_times, first) {
+            command.args(["-ss", &format!("{:.6}", t)]);
+        }
+        command.arg("-i").arg(&self.path)
+            .args(["-map", "0:v:0", "-frames:v", &count.to_string(), "-fps_mode", "passthrough",
+                   "-f", "rawvideo", "-pix_fmt", "rgba", "pipe:1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let mut child = command.spawn().map_err(|e| format!("Could not run ffmpeg, is it installed? ({})", e))?;
+
+        let mut stdout = child.stdout.take().ok_or("Could not read from ffmpeg.")?;
+        let mut buffer = vec![0u8; self.size[0] * self.size[1] * 4];
+        let mut result = Ok(());
+        for frame in first..first + count {
+            if let Err(e) = stdout.read_exact(&mut buffer) {
+                result = Err(format!("Could not decode frame {} ({}).", frame, e));
+                break;
+            }
+            if !f(frame, egui::ColorImage::from_rgba_unmultiplied(self.size, &buffer)) {
+                break;
+            }
+        }
+
+        let _ = child.kill();
+        let _ = child.wait();
+        result
+    }
+}
+
+impl ImageProvider for VideoFile {
+    fn from_path(path: path::PathBuf) -> Result<Self, String> {
+        let mut result = VideoFile {
+            decoder: VideoDecoder::open(path)?,
+            texture_options: egui::TextureOptions::LINEAR,
+            image_cache: Arc::new(Cache::builder()
+                .max_capacity(CACHE_COUNT)
+                .time_to_idle(Duration::from_secs(60))
+                .build()),
+            predictive_frame_sender: None,
+            predictive_loading_thread: None,
+        };
+
+        result.predictive_loader();
+
+        Ok(result)
+    }
+
+    fn frame_count(&self) -> usize {
+        self.decoder.frame_count()
+    }
+
+    fn get_frame(&mut self, frame: usize, texture_handle: &mut Option<egui::TextureHandle>, ctx: &egui::Context) {
+        if frame >= self.frame_count() { return; }
+
+        if let Some(sender) = &self.predictive_frame_sender {
+            let _ = sender.send(frame);
+        }
+
+        let img = match self.image_cache.get(&frame) {
+            Some(img) => Some(img),
+            None => {
+                let mut img = None;
+                let result = self.decoder.decode(frame, 1, |_, i| {
+                    img = Some(Arc::new(i));
+                    false
+                });
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+                if let Some(img) = &img {
+                    self.image_cache.insert(frame, img.clone());
+                }
+                img
+            }
+        };
+
+        if let Some(img) = img {
+            set_texture(texture_handle, img, self.texture_options, ctx);
+        }
+    }
+
+    fn frame_name(&self, frame: usize) -> Option<String> {
+        if frame >= self.frame_count() {
+            return None;
+        }
+        let stem = self.decoder.path.file_stem()?.to_string_lossy().to_string();
+        Some(format!("{}_{:06}.png", stem, frame))
+    }
+
+    fn frame_size(&self, frame: usize) -> Result<[usize; 2], String> {
+        if frame >= self.frame_count() {
+            return Err(format!("Frame {} does not exist.", frame));
+        }
+        Ok(self.decoder.size)
+    }
+}
+
+impl Drop for VideoFile {
+    fn drop(&mut self) {
+        // Dropping the sender stops the predictive loader.
+        self.predictive_frame_sender = None;
+        if let Some(t) = self.predictive_loading_thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl VideoFile {
+    fn predictive_loader(&mut self) {
+        let (sender, receiver) = mpsc::sync_channel::<usize>(1000);
+        self.predictive_frame_sender = Some(sender);
+
+        let image_cache = self.image_cache.clone();
+        let decoder = self.decoder.clone();
+        let frame_count = decoder.frame_count();
+
+        let t = std::thread::spawn(move || {
+            let mut recent_frames = VecDeque::<usize>::new();
+            const MAX_RECENT_FRAMES: usize = 3;
+            let mut next_frame: Option<usize> = None;
+            loop {
+                let frame = match next_frame.take() {
+                    Some(frame) => frame,
+                    None => match receiver.recv() {
+                        Ok(frame) => frame,
+                        Err(_) => return,
+                    }
+                };
+
+                recent_frames.push_back(frame);
+                if recent_frames.len() > MAX_RECENT_FRAMES {
+                    recent_frames.pop_front();
+                }
+                if recent_frames.len() < 2 {
+                    continue;
+                }
+                let up = recent_frames.iter().zip(recent_frames.iter().skip(1)).all(|(a, b)| b > a);
+                let down = recent_frames.iter().zip(recent_frames.iter().skip(1)).all(|(a, b)| b < a);
+
+                // Only frames in the scrolling direction are loaded. Decoding always runs forward,
+                // so when scrolling down, the frames before `frame` are decoded in one go.
+                let (first, last) = if up {
+                    (frame, (frame + PREDICTIVE_LOADING_IMAGE_COUNT).min(frame_count) - 1)
+                } else if down {
+                    ((frame + 1).saturating_sub(PREDICTIVE_LOADING_IMAGE_COUNT), frame)
+                } else {
+                    continue;
+                };
+
+                // Start at the first frame that is not cached yet.
+                let first = match (first..=last).find(|f| !image_cache.contains_key(f)) {
+                    Some(f) => f,
+                    None => continue,
+                };
+
+                let mut stop = false;
+                let result = decoder.decode(first, last - first + 1, |f, image| {
+                    image_cache.insert(f, Arc::new(image));
+                    // A new request within the decoded range does not restart decoding.
+                    match receiver.try_recv() {
+                        Ok(new_frame) => {
+                            next_frame = Some(new_frame);
+                            (first..=last).contains(&new_frame)
+                        },
+                        Err(TryRecvError::Empty) => true,
+                        Err(TryRecvError::Disconnected) => {
+                            stop = true;
+                            false
+                        },
+                    }
+                });
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+                if stop {
+                    return;
+                }
+            }
+        });
+
+        self.predictive_loading_thread = Some(t);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_times_and_seeking() {
+        // B-frames: packets are not in presentation order.
+        let times = parse_frame_times("1.000000\n1.120000,\n1.040000\n1.080000\n", 1.0).unwrap();
+        assert_eq!(times.len(), 4);
+        assert!((times[1] - 0.04).abs() < 1e-9);
+        assert!((times[3] - 0.12).abs() < 1e-9);
+        assert!(parse_frame_times("0.0\nN/A\n", 0.0).is_none());
+
+        assert_eq!(seek_time(&times, 0), None);
+        assert!((seek_time(&times, 2).unwrap() - 0.06).abs() < 1e-9);
+
+        assert_eq!(parse_frame_rate("30000/1001").map(|r| (r * 100.0).round()), Some(2997.0));
+        assert_eq!(parse_frame_rate("0/0"), None);
+    }
+}
@@ -0,0 +1,131 @@
+use egui::{ecolor::Hsva, Color32, Pos2, Rect, Vec2};
+
+/// Largest zoom, in screen points per image pixel.
+const MAX_ZOOM: f32 = 32.0;
+
+/// Zoom and pan of the image canvas.
+/// The canvas has the size of the image shrunk to fit the window ("fit" mode). When zoomed in,
+/// only part of the image is visible in the canvas, and the image can be moved around.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CanvasView {
+    /// Screen points per image pixel, or None to fit the image into the canvas.
+    pub zoom: Option<f32>,
+    /// Position of the top left corner of the image relative to the canvas, when zoomed.
+    pub pan: Vec2,
+}
+
+impl CanvasView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The zoom at which the image of `image_size` pixels fills the `canvas`.
+    pub fn fit_zoom(canvas: Rect, image_size: Vec2) -> f32 {
+        (canvas.width() / image_size.x).min(canvas.height() / image_size.y)
+    }
+
+    /// Screen rectangle of the whole image, which may extend beyond the canvas.
+    pub fn image_rect(&self, canvas: Rect, image_size: Vec2) -> Rect {
+        match self.zoom {
+            None => canvas,
+            Some(zoom) => Rect::from_min_size(canvas.min + self.pan, image_size * zoom),
+        }
+    }
+
+    /// Show the whole image in the canvas.
+    pub fn fit(&mut self) {
+        self.zoom = None;
+        self.pan = Vec2::ZERO;
+    }
+
+    /// Zoom to `zoom` points per image pixel, keeping the image point under `center` in place.
+    pub fn zoom_to(&mut self, zoom: f32, center: Pos2, canvas: Rect, image_size: Vec2) {
+        let fit_zoom = Self::fit_zoom(canvas, image_size);
+        if zoom <= fit_zoom {
+            self.fit();
+            return;
+        }
+        let zoom = zoom.min(MAX_ZOOM);
+
+        let image_rect = self.image_rect(canvas, image_size);
+        let current_zoom = image_rect.width() / image_size.x;
+        let c = center - canvas.min;
+        let pan = image_rect.min - canvas.min;
+        self.pan = c - (c - pan) * (zoom / current_zoom);
+        self.zoom = Some(zoom);
+        self.clamp_pan(canvas, image_size);
+    }
+
+    /// Multiply the zoom by `factor`, keeping the image point under `center` in place.
+    pub fn zoom_by(&mut self, factor: f32, center: Pos2, canvas: Rect, image_size: Vec2) {
+        let current_zoom = self.image_rect(canvas, image_size).width() / image_size.x;
+        self.zoom_to(current_zoom * factor, center, canvas, image_size);
+    }
+
+    /// Move the image by `delta` screen points.
+    pub fn pan_by(&mut self, delta: Vec2, canvas: Rect, image_size: Vec2) {
+        if self.zoom.is_some() {
+            self.pan += delta;
+            self.clamp_pan(canvas, image_size);
+        }
+    }
+
+    /// Keep the canvas covered by the image.
+    fn clamp_pan(&mut self, canvas: Rect, image_size: Vec2) {
+        if let Some(zoom) = self.zoom {
+            let size = image_size * zoom;
+            self.pan.x = self.pan.x.clamp((canvas.width() - size.x).min(0.0), 0.0);
+            self.pan.y = self.pan.y.clamp((canvas.height() - size.y).min(0.0), 0.0);
+        }
+    }
+}
+
+/// A distinct color for index `i`, e.g. of an annotation sequence or a class.
+/// Consecutive indices get very different hues.
+pub fn palette_color(i: usize) -> Color32 {
+    // Stepping by the golden ratio spreads the hues evenly, however many colors are used.
+    let hue = (i as f32 * 0.618_034).fract();
+    Hsva::new(hue, 0.85, 0.95, 1.0).into()
+}
+
+/// Position `p` on the screen in normalized image coordinates, with the image shown in `image_rect`.
+pub fn normalized_pos(p: Pos2, image_rect: Rect) -> Vec2 {
+    (p - image_rect.min) / image_rect.size()
+}
+
+/// Screen position of the normalized image coordinates `p`, with the image shown in `image_rect`.
+pub fn screen_pos(p: Vec2, image_rect: Rect) -> Pos2 {
+    image_rect.min + p * image_rect.size()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_keeps_point_under_cursor() {
+        let canvas = Rect::from_min_size(Pos2::new(10.0, 20.0), Vec2::new(400.0, 300.0));
+        let image_size = Vec2::new(800.0, 600.0);
+        let mut view = CanvasView::new();
+        assert_eq!(view.image_rect(canvas, image_size), canvas);
+
+        let cursor = Pos2::new(110.0, 95.0);
+        let before = normalized_pos(cursor, view.image_rect(canvas, image_size));
+        view.zoom_by(4.0, cursor, canvas, image_size);
+        assert_eq!(view.zoom, Some(2.0));
+        let after = normalized_pos(cursor, view.image_rect(canvas, image_size));
+        assert!((before - after).length() < 1e-5);
+        assert!((screen_pos(after, view.image_rect(canvas, image_size)) - cursor).length() < 1e-3);
+
+        // The image can not be moved out of the canvas.
+        view.pan_by(Vec2::new(1000.0, -5000.0), canvas, image_size);
+        let r = view.image_rect(canvas, image_size);
+        assert_eq!(r.min.x, canvas.min.x);
+        assert_eq!(r.max.y, canvas.max.y);
+
+        // Zooming out below the fit zoom shows the whole image again.
+        view.zoom_by(0.1, cursor, canvas, image_size);
+        assert_eq!(view, CanvasView::new());
+    }
+}
@@ -0,0 +1,91 @@
+use quick_xml::escape::escape;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+impl LabelTask {
+    /// Export the annotations in Pascal VOC format into `dir`, with one XML file per frame named after the image.
+    /// Interpolated boxes are written for every frame, invisible boxes are skipped.
+    /// The `<name>` of each object is the state of the string label named `class_label` (or the first string label),
+    /// the `<bndbox>` is in 1-based pixel coordinates as in the original VOC data.
+    /// With `class_depth`, the taxonomy of the class label is collapsed to that many levels.
+    /// Boxes whose class label is not set are skipped (see `incomplete_report`).
+    pub fn export_voc<P: ImageProvider + ?Sized>(&self, dir: &PathBuf, images: &P, class_label: Option<&str>, class_depth: Option<usize>) -> Result<(), String> {
+        let (class_index, class_config) = self.configs.class_label(class_label, class_depth)?;
+        let class_names = class_config.paths();
+        let folder = dir.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+        for frame in 0..images.frame_count() {
+            let name = images.frame_name(frame).unwrap_or(format!("{}", frame));
+            let [width, height] = images.frame_size(frame)?;
+
+            let mut xml = String::new();
+            xml += "<annotation>\n";
+            xml += &format!("  <folder>{}</folder>\n  <filename>{}</filename>\n", escape(folder.as_str()), escape(name.as_str()));
+            xml += "  <source>\n    <database>labelo</database>\n  </source>\n";
+            xml += &format!("  <size>\n    <width>{}</width>\n    <height>{}</height>\n    <depth>3</depth>\n  </size>\n", width, height);
+            xml += "  <segmented>0</segmented>\n";
+
+            for (i, a) in self.get_all_interpolated_annotations_for_frame(frame) {
+                if a.invisible {
+                    continue;
+                }
+
+                let class = match a.labels.get(class_index) {
+                    Some(Label::S(LabelInstance { state: None, .. })) => continue,
+                    Some(Label::S(l)) => l.state.as_deref().and_then(|s| class_config.class_index(s)).map(|c| &class_names[c]),
+                    _ => None,
+                };
+                let class = class.ok_or(format!("Sequence {} has no valid '{}' label in frame {}.", i, class_config.name, frame))?;
+
+                // Box edges are converted to the 1-based indices of the first and last pixel inside the box.
+                let first_pixel = |v: f32, size: usize| ((v * size as f32).floor() as i64 + 1).clamp(1, size as i64);
+                let last_pixel = |v: f32, size: usize| ((v * size as f32).ceil() as i64).clamp(1, size as i64);
+                let truncated = a.bbox.mins.x < 0.0 || a.bbox.mins.y < 0.0 || a.bbox.maxs.x > 1.0 || a.bbox.maxs.y > 1.0;
+
+                xml += &format!("  <object>\n    <name>{}</name>\n    <pose>Unspecified</pose>\n", escape(class.as_str()));
+                xml += &format!("    <truncated>{}</truncated>\n    <difficult>0</difficult>\n", if truncated { 1 } else { 0 });
+                xml += &format!("    <bndbox>\n      <xmin>{}</xmin>\n      <ymin>{}</ymin>\n      <xmax>{}</xmax>\n      <ymax>{}</ymax>\n    </bndbox>\n",
+                    first_pixel(a.bbox.mins.x, width), first_pixel(a.bbox.mins.y, height),
+                    last_pixel(a.bbox.maxs.x, width), last_pixel(a.bbox.maxs.y, height));
+                xml += "  </object>\n";
+            }
+            xml += "</annotation>\n";
+
+            fs::write(dir.join(Path::new(&name).with_extension("xml")), xml).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn export_voc_truncated_box() {
+        let mut task = LabelTask::new();
+        add_box(&mut task, (-0.1, 0.25), (0.5, 0.755), 0, "possum");
+
+        let images = TestImages { count: 1, size: [200, 100] };
+        let tmp = TempDir::new("export_voc");
+        let dir = tmp.join("voc");
+        task.export_voc(&dir, &images, None, None).unwrap();
+
+        let xml = fs::read_to_string(dir.join("00000.xml")).unwrap();
+        assert!(xml.contains("<name>possum</name>"));
+        assert!(xml.contains("<truncated>1</truncated>"));
+        // 1-based pixels, the box is clamped to the image.
+        assert!(xml.contains("<xmin>1</xmin>\n      <ymin>26</ymin>\n      <xmax>100</xmax>\n      <ymax>76</ymax>"));
+        assert!(xml.contains("<width>200</width>\n    <height>100</height>"));
+    }
+}
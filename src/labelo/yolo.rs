@@ -0,0 +1,101 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::config::*;
+use super::image_provider::ImageProvider;
+use super::labelo::*;
+
+impl LabelTask {
+    /// Export the annotations in YOLO (Darknet/Ultralytics) format into `dir`.
+    /// Writes one `.txt` file per frame, named after the image, with a `class cx cy w h` line per visible box
+    /// in normalized coordinates, as well as `classes.txt` and `data.yaml`.
+    /// The states of the string label named `class_label` (or the first string label) become the class ids.
+    /// With `class_depth`, the taxonomy of the class label is collapsed to that many levels.
+    /// Boxes whose class label is not set are skipped (see `incomplete_report`).
+    pub fn export_yolo<P: ImageProvider + ?Sized>(&self, dir: &PathBuf, images: &P, class_label: Option<&str>, class_depth: Option<usize>) -> Result<(), String> {
+        let (class_index, class_config) = self.configs.class_label(class_label, class_depth)?;
+        let class_names = class_config.paths();
+
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+        for frame in 0..images.frame_count() {
+            let name = images.frame_name(frame).unwrap_or(format!("{}", frame));
+            let mut lines = String::new();
+
+            for (i, seq) in self.sequences.iter().enumerate() {
+                let a = match seq.get_interpolated_annotation_for_frame(frame) {
+                    Some(a) if !a.invisible => a,
+                    _ => continue,
+                };
+
+                let class_id = match a.labels.get(class_index) {
+                    Some(Label::S(LabelInstance { state: None, .. })) => continue,
+                    Some(Label::S(l)) => l.state.as_deref().and_then(|s| class_config.class_index(s)),
+                    _ => None,
+                };
+                let class_id = class_id.ok_or(format!(
+                    "Sequence {} has no valid '{}' label in frame {}.", i, class_config.name, frame))?;
+
+                // YOLO boxes must lie within the image.
+                let x0 = a.bbox.mins.x.clamp(0.0, 1.0);
+                let y0 = a.bbox.mins.y.clamp(0.0, 1.0);
+                let x1 = a.bbox.maxs.x.clamp(0.0, 1.0);
+                let y1 = a.bbox.maxs.y.clamp(0.0, 1.0);
+                if x1 <= x0 || y1 <= y0 {
+                    continue;
+                }
+
+                lines += &format!("{} {:.6} {:.6} {:.6} {:.6}\n",
+                    class_id, (x0 + x1) / 2.0, (y0 + y1) / 2.0, x1 - x0, y1 - y0);
+            }
+
+            // Frames without boxes get an empty file, so they are used as negative examples.
+            let label_path = dir.join(Path::new(&name).with_extension("txt"));
+            fs::write(&label_path, lines).map_err(|e| e.to_string())?;
+        }
+
+        let mut classes = fs::File::create(dir.join("classes.txt")).map_err(|e| e.to_string())?;
+        for s in &class_names {
+            writeln!(classes, "{}", s).map_err(|e| e.to_string())?;
+        }
+
+        // JSON strings are valid YAML scalars, so they are used for quoting.
+        let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+        // Ultralytics resolves relative dataset paths against its own datasets directory.
+        let dir_string = dir.canonicalize().unwrap_or(dir.clone()).to_string_lossy().to_string();
+        let mut yaml = format!("path: {}\ntrain: .\nval: .\nnc: {}\nnames:\n", quote(&dir_string), class_names.len());
+        for (i, s) in class_names.iter().enumerate() {
+            yaml += &format!("  {}: {}\n", i, quote(s));
+        }
+        fs::write(dir.join("data.yaml"), yaml).map_err(|e| e.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labelo::image_provider::tests::TestImages;
+    use crate::labelo::labelo::tests::{add_box, TempDir};
+
+    #[test]
+    fn export_yolo_clamped_boxes() {
+        let mut task = LabelTask::new();
+        let mut a = add_box(&mut task, (0.25, 0.5), (1.25, 1.0), 0, "dog");
+        a.invisible = true;
+        task.sequences[0].edit_annotation(2, &a);
+
+        let images = TestImages { count: 3, size: [200, 100] };
+        let tmp = TempDir::new("export_yolo");
+        let dir = tmp.join("yolo");
+        task.export_yolo(&dir, &images, None, None).unwrap();
+
+        // The box is clamped to the image.
+        assert_eq!(fs::read_to_string(dir.join("00000.txt")).unwrap(), "1 0.625000 0.750000 0.750000 0.500000\n");
+        assert_eq!(fs::read_to_string(dir.join("00001.txt")).unwrap(), "1 0.625000 0.750000 0.750000 0.500000\n");
+        assert_eq!(fs::read_to_string(dir.join("00002.txt")).unwrap(), "");
+        assert_eq!(fs::read_to_string(dir.join("classes.txt")).unwrap(), "cat\ndog\npossum\n");
+        assert!(fs::read_to_string(dir.join("data.yaml")).unwrap().contains("nc: 3\nnames:\n  0: \"cat\"\n"));
+    }
+}
@@ -1,7 +1,6 @@
 use eframe::{egui::{self, Pos2, Rect, RichText, Rounding, Sense, Stroke, Vec2}, 
     epaint::TextureHandle,
     glow::Texture};
-use crate::egui::load::SizedTexture;
 use egui_extras;
 use egui_dialogs::{self, DialogDetails, StandardReply};
 use image::{
@@ -10,7 +9,7 @@ use image::{
 use labelo::*;
 use parry2d::{bounding_volume::Aabb, na::OPoint, math::Point, query::PointQuery};
 
-use std::{borrow::BorrowMut, cell::RefCell, env::join_paths, path::{Path, PathBuf}, process::exit, rc::Rc, str::FromStr};
+use std::{borrow::BorrowMut, cell::RefCell, env::join_paths, path::{Path, PathBuf}, process::exit, rc::Rc, str::FromStr, time::{Duration, Instant}};
 use std::{
     fs::{self, DirEntry},
     io,
@@ -22,6 +21,10 @@ mod labelo;
 use labelo::labelo::*;
 use labelo::config::*;
 use labelo::image_provider::*;
+use labelo::history::*;
+use labelo::view::*;
+use labelo::keybindings::*;
+use labelo::playback::*;
 
 use egui::{ecolor::Color32, ColorImage, TextBuffer, Ui};
 
@@ -37,18 +40,151 @@ struct Cli {
     /// Label configuration file, defining which labels to use.
     #[arg(short, long)]
     label_config: Option<PathBuf>,
-    /// Input directory containing the images.
+    /// Input directory containing the images, or a video file if labelo is built with the `video` feature.
     #[arg(short, long)]
     input_dir: Option<PathBuf>,
     /// Output label file (json format). If the file exists, it will be read at startup.
     #[arg(short, long, default_value = "labels.json")]
     output_file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 } 
 
+/// Commands that run without opening a window. Without a command, the annotation GUI is started.
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+    /// Convert a label file to another format.
+    Export {
+        /// Output format.
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+        /// Input directory containing the images, or a video file if labelo is built with the `video` feature.
+        #[arg(short, long)]
+        input_dir: PathBuf,
+        /// Label configuration file. Defaults to ~/.labelo_config.toml, or the built-in default config.
+        #[arg(short, long)]
+        label_config: Option<PathBuf>,
+        /// Name of the string label used as object class. Defaults to the first string label.
+        #[arg(short, long)]
+        class_label: Option<String>,
+        /// Collapse the class taxonomy to this many levels, e.g. 1 to export only the top level classes.
+        #[arg(long)]
+        class_depth: Option<usize>,
+        /// Label file (json format) written by labelo.
+        labels: PathBuf,
+        /// Output file, or output directory for yolo and voc.
+        output: PathBuf,
+    },
+    /// Print statistics about a label file.
+    Stats {
+        /// Input directory containing the images, or a video file if labelo is built with the `video` feature.
+        #[arg(short, long)]
+        input_dir: PathBuf,
+        /// Label configuration file. Defaults to ~/.labelo_config.toml, or the built-in default config.
+        #[arg(short, long)]
+        label_config: Option<PathBuf>,
+        /// Name of the string label used as object class. Defaults to the first string label.
+        #[arg(short, long)]
+        class_label: Option<String>,
+        /// Print the statistics as json instead of a table.
+        #[arg(long)]
+        json: bool,
+        /// Label file (json format) written by labelo.
+        labels: PathBuf,
+    },
+    /// Check a label file against the label config. Exits with a non-zero code if problems are found.
+    Validate {
+        /// Label configuration file. Defaults to ~/.labelo_config.toml, or the built-in default config.
+        #[arg(short, long)]
+        label_config: Option<PathBuf>,
+        /// Label file (json format) written by labelo.
+        labels: PathBuf,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum ExportFormat {
+    Coco,
+    Yolo,
+    Mot,
+    Csv,
+    Voc,
+    Cvat,
+}
+
+/// Create a LabelTask with the annotations from `labels`, using the label config file if given,
+/// otherwise ~/.labelo_config.toml if it exists, otherwise the default config.
+fn load_label_task(label_config: &Option<PathBuf>, labels: &PathBuf, load_only_keyframes: bool) -> Result<LabelTask, String> {
+    let mut label_task = LabelTask::new();
+    let default_config = dirs::home_dir().unwrap_or_default().join(".labelo_config.toml");
+    match label_config {
+        Some(config) => label_task.load_label_configs(config)?,
+        None if default_config.exists() => label_task.load_label_configs(&default_config)?,
+        None => {}
+    }
+    label_task.load_annotations(labels, load_only_keyframes)?;
+    Ok(label_task)
+}
+
+fn run_command(command: &Command) -> Result<(), String> {
+    match command {
+        Command::Export { format, input_dir, label_config, class_label, class_depth, labels, output } => {
+            let label_task = load_label_task(label_config, labels, true)?;
+            let images = open_image_provider(input_dir.clone())?;
+            let images = images.as_ref();
+            let class_label = class_label.as_deref();
+            if let Some(report) = label_task.incomplete_report(class_label) {
+                eprintln!("Warning: {}", report);
+            }
+            match format {
+                ExportFormat::Coco => label_task.export_coco(output, images, class_label, *class_depth),
+                ExportFormat::Yolo => label_task.export_yolo(output, images, class_label, *class_depth),
+                ExportFormat::Mot => label_task.export_mot(output, images, class_label, *class_depth),
+                ExportFormat::Csv => label_task.export_csv(output, images),
+                ExportFormat::Voc => label_task.export_voc(output, images, class_label, *class_depth),
+                ExportFormat::Cvat => label_task.export_cvat(output, images, class_label, *class_depth),
+            }
+        },
+        Command::Stats { input_dir, label_config, class_label, json, labels } => {
+            let label_task = load_label_task(label_config, labels, true)?;
+            let images = open_image_provider(input_dir.clone())?;
+            let stats = label_task.statistics(images.frame_count(), class_label.as_deref());
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats).map_err(|e| e.to_string())?);
+            } else {
+                print!("{}", stats);
+            }
+            Ok(())
+        },
+        Command::Validate { label_config, labels } => {
+            // All annotations are checked, not only keyframes.
+            let label_task = load_label_task(label_config, labels, false)?;
+            let issues = label_task.validate();
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if issues.is_empty() {
+                println!("No problems found.");
+                Ok(())
+            } else {
+                Err(format!("Found {} problems in {}.", issues.len(), labels.to_string_lossy()))
+            }
+        }
+    }
+}
+
 fn main() -> Result<(), eframe::Error> {
 
     let args = Cli::parse();
 
+    if let Some(command) = &args.command {
+        if let Err(e) = run_command(command) {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
         ..Default::default()
@@ -69,7 +205,7 @@ struct MyApp<'a> {
     first_update: bool,
 
     images_dir: Option<PathBuf>,
-    image_provider: ImageDirectory,
+    image_provider: Box<dyn ImageProvider>,
 
     current_image: usize,
     previous_image: usize,
@@ -77,26 +213,58 @@ struct MyApp<'a> {
     label_configs_filename: PathBuf,
     labels_filename: PathBuf,
     label_task: LabelTask,
+    /// The keyboard shortcuts of the label config, parsed when it is loaded.
+    shortcuts: Vec<(egui::KeyboardShortcut, KeyAction)>,
+    /// Undo/redo history of the edits of `label_task`.
+    history: History,
     annotation_action: AnnotationAction,
     /// The annotation that is currently being seen in the frame, in the currently active annotation sequence.
     /// This is NOT the object that with_current_annotation() is working on.
     /// This object is used by the labelling pane to display the annotation object properties.
     current_annotation_copy: Option<Annotation>,
+    /// The annotation sequence `current_annotation_copy` was taken from.
+    current_annotation_copy_sequence: usize,
 
     texture: Option<egui::TextureHandle>,
+    /// Zoom and pan of the image.
+    view: CanvasView,
+    /// Screen rectangle of the image canvas in the last update.
+    canvas_rect: Rect,
 
     drag_start_position: Vec2,
+    /// Annotation sequence and current frame of the keyframe that is being dragged in the timeline.
+    timeline_drag: Option<(usize, usize)>,
+    /// First and last frame of the range that "Clear frames" deletes the keyframes of.
+    clear_range: [usize; 2],
 
     dialogs: egui_dialogs::Dialogs<'a>,
+    /// Annotations from the recovery file, while the user is asked whether to restore them.
+    recovered_sequences: Option<Vec<AnnotationSequence>>,
 
-    play_mode: bool,
-    play_fps: usize,
+    /// The annotations as they were last saved to `labels_filename`.
+    saved_sequences: Vec<AnnotationSequence>,
+    /// The annotations as they were last written to the recovery file.
+    autosaved_sequences: Vec<AnnotationSequence>,
+    last_autosave: Instant,
+
+    /// Draw the sequence index and label states above every box.
+    show_captions: bool,
+    /// Color boxes by their class label instead of by annotation sequence.
+    color_by_class: bool,
+
+    playback: Playback,
 }
 
 
 impl<'a> MyApp<'a> {
 
     const CORNER_CATCH_RADIUS: f32 = 10.0;
+    const TIMELINE_ROW_HEIGHT: f32 = 14.0;
+    const TIMELINE_MAX_HEIGHT: f32 = 150.0;
+    /// Distance in points within which a keyframe marker in the timeline can be grabbed.
+    const KEYFRAME_CATCH_RADIUS: f32 = 5.0;
+    const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+    const RESTORE_DIALOG_ID: &'static str = "restore_recovery_file";
 
     fn new() -> Self {
 
@@ -118,35 +286,119 @@ impl<'a> MyApp<'a> {
         }
         
         let mut label_task = LabelTask::new();
-        let _ = label_task.load_label_configs(&config_filepath);
+        if let Err(e) = label_task.load_label_configs(&config_filepath) {
+            println!("Error: Could not load the label config {} ({}).", config_filepath.to_string_lossy(), e);
+        }
+        let shortcuts = label_task.configs.keybindings.shortcuts().unwrap_or_default();
         let mut result = Self {
             first_update: true,
             images_dir: args.input_dir,
-            image_provider: ImageDirectory::default(),
+            image_provider: Box::new(ImageDirectory::default()),
             // image_stack: vec![],
             current_image: 0,
             previous_image: 0,
             label_configs_filename: config_filepath,
             labels_filename: args.output_file,
             label_task: label_task,
+            shortcuts,
+            history: History::new(),
             annotation_action: AnnotationAction::new(),
             current_annotation_copy: None,
+            current_annotation_copy_sequence: 0,
             texture: None,
+            view: CanvasView::new(),
+            canvas_rect: Rect::NOTHING,
             drag_start_position: Vec2::new(0.0, 0.0),
+            timeline_drag: None,
+            clear_range: [0, 0],
             dialogs: egui_dialogs::Dialogs::new(),
-            play_mode: false,
-            play_fps: 30,
+            recovered_sequences: None,
+            saved_sequences: vec![],
+            autosaved_sequences: vec![],
+            last_autosave: Instant::now(),
+            show_captions: true,
+            color_by_class: false,
+            playback: Playback::new(),
         };
 
         result.label_task.load_annotations(&result.labels_filename, true);
+        result.saved_sequences = result.label_task.sequences.clone();
+        result.autosaved_sequences = result.label_task.sequences.clone();
+        result.check_recovery_file();
         result
     }
 
 
+    /// The file that annotations are periodically saved to, next to the label file (e.g. labels.json.autosave).
+    fn recovery_filename(&self) -> PathBuf {
+        let mut name = self.labels_filename.file_name().unwrap_or_default().to_os_string();
+        name.push(".autosave");
+        self.labels_filename.with_file_name(name)
+    }
+
+
+    /// Ask the user whether to restore the annotations from the recovery file,
+    /// if it is newer than the label file and contains different annotations.
+    fn check_recovery_file(&mut self) {
+        let recovery_filename = self.recovery_filename();
+        let modified = |p: &PathBuf| fs::metadata(p).and_then(|m| m.modified()).ok();
+        let recovery_is_newer = match (modified(&recovery_filename), modified(&self.labels_filename)) {
+            (Some(recovery), Some(labels)) => recovery > labels,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        if !recovery_is_newer {
+            return;
+        }
+
+        let mut recovered = LabelTask::new();
+        // The track labels are restored according to the label config.
+        recovered.configs = self.label_task.configs.clone();
+        if recovered.load_annotations(&recovery_filename, true).is_ok() && recovered.sequences != self.label_task.sequences {
+            self.recovered_sequences = Some(recovered.sequences);
+            let message = format!("{} contains annotations that are newer than {}, probably because labelo was not closed properly. Restore them?",
+                recovery_filename.to_string_lossy(), self.labels_filename.to_string_lossy());
+            self.dialogs.add(DialogDetails::confirm("Restore annotations", message).with_id(Self::RESTORE_DIALOG_ID));
+        }
+    }
+
+
+    /// Save the annotations to the label file. The recovery file is removed afterwards, since it is outdated.
+    fn save_annotations(&mut self) -> Result<(), String> {
+        self.label_task.save_annotations(&self.labels_filename, self.image_provider.frame_count(), true)?;
+        self.saved_sequences = self.label_task.sequences.clone();
+        self.autosaved_sequences = self.label_task.sequences.clone();
+        let _ = fs::remove_file(self.recovery_filename());
+        Ok(())
+    }
+
+
+    /// Save the annotations to the recovery file if they changed since the last autosave
+    /// and `AUTOSAVE_INTERVAL` has passed.
+    fn autosave(&mut self) {
+        if self.last_autosave.elapsed() < Self::AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.last_autosave = Instant::now();
+
+        // Without images, saving would drop all annotations.
+        if self.image_provider.frame_count() == 0 || self.label_task.sequences == self.autosaved_sequences {
+            return;
+        }
+
+        match self.label_task.save_annotations(&self.recovery_filename(), self.image_provider.frame_count(), true) {
+            Ok(_) => self.autosaved_sequences = self.label_task.sequences.clone(),
+            Err(e) => println!("Autosaving annotations did not work ({}).", e),
+        }
+    }
+
+
 
     fn open_dir(&mut self, dir: &PathBuf) -> Result<(), String> {
-        let image_provider = ImageDirectory::from_path(dir.clone())?;
+        let image_provider = open_image_provider(dir.clone())?;
         self.image_provider = image_provider;
+        self.images_dir = Some(dir.clone());
+        self.view.fit();
         return Ok(());
     }
 
@@ -168,6 +420,254 @@ impl<'a> MyApp<'a> {
     }
 
 
+    /// Run the export `f` in `format`. Errors, and the annotations with required labels that are not set,
+    /// are printed and shown in a dialog.
+    fn export<F: FnOnce(&LabelTask, &dyn ImageProvider) -> Result<(), String>>(&mut self, format: &str, f: F) {
+        match f(&self.label_task, self.image_provider.as_ref()) {
+            Err(e) => {
+                let message = format!("Exporting {} annotations did not work ({}).", format, e);
+                eprintln!("{}", message);
+                self.dialogs.add(DialogDetails::error(format!("Export {}", format), message));
+            },
+            Ok(()) => {
+                if let Some(report) = self.label_task.incomplete_report(None) {
+                    eprintln!("Warning: {}", report);
+                    self.dialogs.add(DialogDetails::warning(format!("Export {}", format), report));
+                }
+            },
+        }
+    }
+
+
+    /// Start a new label task with the label config from `label_configs_filename` and parse its keyboard shortcuts.
+    fn reload_label_configs(&mut self) {
+        self.label_task = LabelTask::new();
+        if let Err(e) = self.label_task.load_label_configs(&self.label_configs_filename) {
+            println!("Error: Could not load the label config {} ({}).", self.label_configs_filename.to_string_lossy(), e);
+        }
+        self.shortcuts = self.label_task.configs.keybindings.shortcuts().unwrap_or_default();
+        self.history.clear();
+    }
+
+
+    /// Take `current_annotation_copy` from the current annotation sequence. If it has no box in the current frame,
+    /// its first keyframe is used, so that the label pane never shows (and writes) the labels of another sequence.
+    fn reload_current_annotation_copy(&mut self) {
+        let frame = self.current_image;
+        self.current_annotation_copy = self.label_task.sequences.get(self.label_task.current_sequence).and_then(|s| {
+            s.get_interpolated_annotation_for_frame(frame)
+                .or_else(|| s.annotations.first().and_then(|a| s.get_interpolated_annotation_for_frame(a.frame)))
+        });
+        self.current_annotation_copy_sequence = self.label_task.current_sequence;
+    }
+
+
+    /// Change the current annotation sequence with `f`, which gets the current frame, and record the change.
+    fn edit_current_sequence<F: FnOnce(&mut AnnotationSequence, usize)>(&mut self, f: F) {
+        if self.label_task.has_sequences() {
+            let (frame, current) = (self.current_image, self.label_task.current_sequence);
+            self.history.edit_sequence(&mut self.label_task, current, |s| f(s, frame));
+        }
+    }
+
+
+    /// Change the labels or the invisible flag of the current sequence in the current frame with `f`, and record the change.
+    /// An interpolated annotation becomes a keyframe, unless only labels with scope 'track' changed.
+    fn edit_current_annotation<F: FnOnce(&mut Annotation)>(&mut self, f: F) {
+        if self.label_task.has_sequences() {
+            let (frame, current) = (self.current_image, self.label_task.current_sequence);
+            self.history.edit_sequence(&mut self.label_task, current, |s| {
+                if let Some(mut a) = s.get_interpolated_annotation_for_frame(frame) {
+                    f(&mut a);
+                    s.edit_labels(frame, &a);
+                }
+            });
+        }
+    }
+
+
+    /// Run the action of a keyboard shortcut.
+    fn handle_key_action(&mut self, action: KeyAction) {
+        let frame_count = self.image_provider.frame_count();
+        let sequence_count = self.label_task.sequences.len();
+        let keyframes: Vec<usize> = match self.label_task.sequences.get(self.label_task.current_sequence) {
+            Some(s) => s.annotations.iter().map(|a| a.frame).collect(),
+            None => vec![],
+        };
+
+        match action {
+            KeyAction::PreviousFrame => {
+                self.current_image = (self.current_image as i32 - 1).rem_euclid(frame_count as i32) as usize;
+            },
+            KeyAction::NextFrame => {
+                self.current_image = ((self.current_image + 1) as i32).rem_euclid(frame_count as i32) as usize;
+            },
+            KeyAction::PreviousKeyframe => {
+                if let Some(frame) = keyframes.iter().rev().find(|f| **f < self.current_image) {
+                    self.current_image = *frame;
+                }
+            },
+            KeyAction::NextKeyframe => {
+                if let Some(frame) = keyframes.iter().find(|f| **f > self.current_image) {
+                    self.current_image = (*frame).min(frame_count - 1);
+                }
+            },
+            KeyAction::PreviousSequence => {
+                if sequence_count > 0 {
+                    self.label_task.current_sequence = (self.label_task.current_sequence + sequence_count - 1) % sequence_count;
+                }
+            },
+            KeyAction::NextSequence => {
+                if sequence_count > 0 {
+                    self.label_task.current_sequence = (self.label_task.current_sequence + 1) % sequence_count;
+                }
+            },
+            KeyAction::NewSequence => {
+                let sequence = self.label_task.new_sequence();
+                self.history.add_sequence(&mut self.label_task, sequence);
+            },
+            KeyAction::DeleteKeyframe => {
+                self.edit_current_sequence(|s, frame| {
+                    s.delete_keyframe(frame);
+                });
+            },
+            KeyAction::ToggleInvisible => {
+                self.edit_current_annotation(|a| a.invisible = !a.invisible);
+            },
+            KeyAction::PlayPause => {
+                self.playback.toggle();
+            },
+            KeyAction::Save => {
+                if let Err(e) = self.save_annotations() {
+                    println!("Saving annotations did not work ({}).", e);
+                }
+            },
+            KeyAction::Quit => {
+                match self.save_annotations() {
+                    Ok(_) => exit(1),
+                    Err(e) => println!("Saving annotations did not work ({}).", e),
+                }
+            },
+            KeyAction::Undo => {
+                self.history.undo(&mut self.label_task);
+            },
+            KeyAction::Redo => {
+                self.history.redo(&mut self.label_task);
+            },
+            KeyAction::LabelState(state) => {
+                if let Ok((index, lcs)) = self.label_task.configs.class_label(None, None) {
+                    if let Some(state) = lcs.paths().get(state).cloned() {
+                        self.edit_current_annotation(|a| {
+                            if let Some(Label::S(l)) = a.labels.get_mut(index) {
+                                l.state = Some(state);
+                            }
+                        });
+                    }
+                }
+            },
+        }
+    }
+
+
+    /// Color of the annotation sequence `i`, or of the class in `labels` when coloring by class.
+    fn track_color(&self, i: usize, labels: &[Label]) -> Color32 {
+        if self.color_by_class {
+            if let Ok((class_index, class_config)) = self.label_task.configs.class_label(None, None) {
+                if let Some(Label::S(l)) = labels.get(class_index) {
+                    if let Some(class) = l.state.as_deref().and_then(|s| class_config.state_index(s)) {
+                        return palette_color(class);
+                    }
+                }
+            }
+        }
+        palette_color(i)
+    }
+
+
+    /// Timeline with one row per annotation sequence, showing where the sequence has keyframes
+    /// and where it is invisible. Clicking seeks to the frame, keyframes can be dragged to other frames.
+    fn timeline(&mut self, ui: &mut Ui) {
+        let frame_count = self.image_provider.frame_count();
+        if frame_count == 0 || self.label_task.sequences.is_empty() {
+            return;
+        }
+
+        egui::ScrollArea::vertical().max_height(Self::TIMELINE_MAX_HEIGHT).show(ui, |ui| {
+            ui.spacing_mut().item_spacing.y = 1.0;
+            for i in 0..self.label_task.sequences.len() {
+                let (rect, response) = ui.allocate_exact_size(Vec2::new(ui.available_width(), Self::TIMELINE_ROW_HEIGHT), Sense::click_and_drag());
+                let frame_x = |frame: usize| rect.left() + frame as f32 / frame_count as f32 * rect.width();
+                let x_frame = |x: f32| (((x - rect.left()) / rect.width() * frame_count as f32).max(0.0) as usize).min(frame_count - 1);
+                let frame_width = rect.width() / frame_count as f32;
+
+                let painter = ui.painter_at(rect);
+                let background = if i == self.label_task.current_sequence { Color32::from_gray(70) } else { Color32::from_gray(40) };
+                painter.rect_filled(rect, Rounding::ZERO, background);
+
+                // Each keyframe holds until the next one, the last one until the end.
+                let annotations = &self.label_task.sequences[i].annotations;
+                for (k, a) in annotations.iter().enumerate() {
+                    let end = annotations.get(k + 1).map_or(frame_count, |b| b.frame);
+                    let span = Rect::from_x_y_ranges(frame_x(a.frame)..=frame_x(end), rect.y_range());
+                    let color = if a.invisible { Color32::from_gray(90) } else { self.track_color(i, &a.labels).gamma_multiply(0.4) };
+                    painter.rect_filled(span.shrink2(Vec2::new(0.0, 3.0)), Rounding::ZERO, color);
+                }
+                for a in annotations {
+                    let x = frame_x(a.frame) + frame_width / 2.0;
+                    let color = if a.invisible { Color32::GRAY } else { self.track_color(i, &a.labels) };
+                    painter.vline(x, rect.y_range(), Stroke::new(frame_width.clamp(2.0, 4.0), color));
+                }
+                painter.text(rect.left_center() + Vec2::new(2.0, 0.0), egui::Align2::LEFT_CENTER, format!("{}", i),
+                    egui::FontId::monospace(10.0), Color32::WHITE);
+
+                let x = frame_x(self.current_image) + frame_width / 2.0;
+                painter.vline(x, rect.y_range(), Stroke::new(1.0, Color32::YELLOW));
+
+                let response = response.on_hover_text(format!("Annotation sequence {}", i));
+
+                if response.drag_started() {
+                    if let Some(p) = response.interact_pointer_pos() {
+                        let closest = annotations.iter()
+                            .map(|a| (a.frame, (frame_x(a.frame) + frame_width / 2.0 - p.x).abs()))
+                            .min_by(|a, b| a.1.total_cmp(&b.1));
+                        if let Some((frame, distance)) = closest {
+                            if distance < Self::KEYFRAME_CATCH_RADIUS.max(frame_width / 2.0) {
+                                self.history.begin(&self.label_task, i);
+                                self.timeline_drag = Some((i, frame));
+                            }
+                        }
+                    }
+                }
+
+                if let Some(p) = response.interact_pointer_pos() {
+                    let frame = x_frame(p.x);
+                    match self.timeline_drag {
+                        Some((sequence, from)) if sequence == i => {
+                            if response.dragged() {
+                                if let Some(to) = self.label_task.sequences[i].move_keyframe(from, frame) {
+                                    self.timeline_drag = Some((i, to));
+                                    self.current_image = to;
+                                }
+                            }
+                        },
+                        _ => {
+                            if response.clicked() || response.dragged() {
+                                self.label_task.current_sequence = i;
+                                self.current_image = frame;
+                            }
+                        }
+                    }
+                }
+
+                if response.drag_stopped() && self.timeline_drag.is_some_and(|(sequence, _)| sequence == i) {
+                    self.timeline_drag = None;
+                    self.history.commit(&self.label_task);
+                }
+            }
+        });
+    }
+
+
     fn with_current_annotation<F: Fn(&mut Annotation) -> ()>(&mut self, f: F) {
         if self.label_task.has_sequences() {
             let s = &mut self.label_task.sequences[self.label_task.current_sequence];
@@ -191,62 +691,154 @@ impl<'a> Default for MyApp<'a> {
 }
 
 
-fn draw_annotation(response: &egui::Response, ui: &mut egui::Ui, annotation: &Annotation, is_active: bool) {
+/// Selectable states of a string label, with the groups of a taxonomy shown as collapsible subtrees.
+/// Groups containing the current `state` are open initially. Returns true if the state was changed.
+fn state_tree(ui: &mut Ui, nodes: &[StateNode], parent: &str, state: &mut Option<String>) -> bool {
+    let mut changed = false;
+    for node in nodes {
+        let path = join_state_path(parent, node.name());
+        match node {
+            StateNode::State(name) => {
+                changed |= ui.selectable_value(state, Some(path), name).changed();
+            },
+            StateNode::Group { name, states } => {
+                let id = ui.make_persistent_id(&path);
+                let open = state.as_ref().is_some_and(|s| s.starts_with(&join_state_path(&path, "")));
+                egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, open)
+                    .show_header(ui, |ui| {
+                        changed |= ui.selectable_value(state, Some(path.clone()), name).changed();
+                    })
+                    .body(|ui| {
+                        changed |= state_tree(ui, states, &path, state);
+                    });
+            },
+        }
+    }
+    changed
+}
+
+/// Edit the value of a label with `widget`. If the label is not set, a button with `title` sets it to `initial`.
+/// If `can_unset`, a button next to the widget unsets it again. Returns true if the value was changed.
+fn optional_value<T>(ui: &mut Ui, state: &mut Option<T>, initial: T, can_unset: bool, title: &str,
+                     widget: impl FnOnce(&mut Ui, &mut T) -> egui::Response) -> bool {
+    ui.horizontal(|ui| {
+        match state {
+            Some(value) => {
+                let changed = widget(ui, value).changed();
+                if can_unset && unset_button(ui) {
+                    *state = None;
+                    return true;
+                }
+                changed
+            },
+            None => {
+                let clicked = ui.button(format!("Set {}", title)).clicked();
+                if clicked {
+                    *state = Some(initial);
+                }
+                clicked
+            }
+        }
+    }).inner
+}
+
+/// Small button to unset an optional label. Returns true if it was clicked.
+fn unset_button(ui: &mut Ui) -> bool {
+    ui.small_button("×").on_hover_text("Unset").clicked()
+}
+
+/// Caption of a box, with the index of its annotation sequence and the states of its labels that are set.
+/// `missing` are the names of required labels that are not set.
+fn annotation_caption(sequence: usize, annotation: &Annotation, missing: &[&str]) -> String {
+    let states: Vec<String> = annotation.labels.iter().filter(|l| l.is_set()).map(|l| l.state_string()).collect();
+    let mut caption = format!("{}: {}", sequence, states.join(", "));
+    if !missing.is_empty() {
+        caption += &format!(" (not set: {})", missing.join(", "));
+    }
+    caption
+}
+
+
+/// Draw the box of `annotation` with `painter`, with the image shown in `image_rect`.
+/// Keyframes are drawn with a thicker line than interpolated boxes. The caption, if any, is drawn above the box.
+fn draw_annotation(response: &egui::Response, painter: &egui::Painter, image_rect: Rect, annotation: &Annotation, is_active: bool,
+                   color: Color32, caption: Option<String>) {
 
     if annotation.invisible {
         return;
     }
 
-    let r = response.rect;
-    let w = r.width();
-    let h = r.height();
-    let p0 = r.left_top();
-    
-    let rr = Rect::from_min_max(Pos2::new(annotation.bbox.mins.x * w + p0.x, annotation.bbox.mins.y * h + p0.y), 
-                                      Pos2::new(annotation.bbox.maxs.x * w + p0.x, annotation.bbox.maxs.y * h + p0.y));
+    let rr = Rect::from_min_max(screen_pos(Vec2::new(annotation.bbox.mins.x, annotation.bbox.mins.y), image_rect),
+                                screen_pos(Vec2::new(annotation.bbox.maxs.x, annotation.bbox.maxs.y), image_rect));
 
     let st = if annotation.interpolated {
-        Stroke::new(4.0, Color32::BLUE)
+        Stroke::new(2.0, color)
     } else {
-        Stroke::new(4.0, Color32::RED)
+        Stroke::new(4.0, color)
     };
 
+    if let Some(caption) = caption {
+        let galley = painter.layout_no_wrap(caption, egui::FontId::proportional(12.0), Color32::BLACK);
+        let caption_rect = Rect::from_min_size(rr.left_top() - Vec2::new(0.0, galley.size().y + 2.0), galley.size() + Vec2::new(4.0, 2.0));
+        painter.rect_filled(caption_rect, Rounding::ZERO, color);
+        painter.galley(caption_rect.min + Vec2::new(2.0, 1.0), galley, Color32::BLACK);
+    }
+
     if is_active {
-        ui.painter().rect(rr, Rounding::ZERO, Color32::TRANSPARENT, st);
+        painter.rect(rr, Rounding::ZERO, Color32::TRANSPARENT, st);
 
         if let Some(hover_pos) = response.hover_pos() {
-            let p = normalized_pos(hover_pos, &response);
+            let p = normalized_pos(hover_pos, image_rect);
             let (_corner, corner_dist, corner_point) = annotation.closest_corner_point(p.x, p.y);
                 
-            if corner_dist * response.rect.width() < MyApp::CORNER_CATCH_RADIUS {
-                let x = corner_point.x * w + p0.x;
-                let y = corner_point.y * h + p0.y;
-                ui.painter().circle_filled(Pos2::new(x, y), MyApp::CORNER_CATCH_RADIUS, Color32::from_rgba_unmultiplied(0, 128, 0, 64));
+            if corner_dist * image_rect.width() < MyApp::CORNER_CATCH_RADIUS {
+                let corner = screen_pos(Vec2::new(corner_point.x, corner_point.y), image_rect);
+                painter.circle_filled(corner, MyApp::CORNER_CATCH_RADIUS, Color32::from_rgba_unmultiplied(0, 128, 0, 64));
             } 
         }
     } else {
-        ui.painter().rect(rr, Rounding::ZERO, Color32::from_rgba_unmultiplied(200, 0, 0, 32), st);
+        painter.rect(rr, Rounding::ZERO, color.gamma_multiply(0.15), st);
     }
 }
 
 
-fn normalized_pos(p: egui::Pos2, response: &egui::Response) -> Vec2 {
-    let sz = response.rect.size();
-    let p0 = response.rect.left_top();
-    // Normalized clicked point:
-    let p = (p - p0) / sz ;
-    p
-}
-
-
 impl<'a> eframe::App for MyApp<'a> {
 
+    /// Save unsaved changes when the window is closed.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.image_provider.frame_count() > 0 && self.label_task.sequences != self.saved_sequences {
+            if let Err(e) = self.save_annotations() {
+                println!("Saving annotations did not work ({}).", e);
+            }
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.dialogs.show(ctx);
+        if let Some(response) = self.dialogs.show(ctx) {
+            if response.is_reply_of(Self::RESTORE_DIALOG_ID) {
+                let recovered = self.recovered_sequences.take();
+                match (response.reply::<StandardReply>(), recovered) {
+                    (Ok(reply), Some(recovered)) if reply.accepted() => {
+                        self.label_task.sequences = recovered;
+                        self.label_task.current_sequence = 0;
+                        self.history.clear();
+                    },
+                    _ => {
+                        let _ = fs::remove_file(self.recovery_filename());
+                    }
+                }
+            }
+        }
+
+        self.autosave();
+        ctx.request_repaint_after(Self::AUTOSAVE_INTERVAL);
 
         if self.first_update {
             if let Some(p) = &self.images_dir.clone() {
-                self.image_provider = ImageDirectory::from_path(p.clone()).unwrap_or(ImageDirectory::default());
+                self.image_provider = open_image_provider(p.clone()).unwrap_or_else(|e| {
+                    println!("Error: Could not open {} ({}).", p.to_string_lossy(), e);
+                    Box::new(ImageDirectory::default())
+                });
                 self.current_image = 0;
                 self.previous_image = 1;
             }
@@ -254,11 +846,10 @@ impl<'a> eframe::App for MyApp<'a> {
             self.first_update = false;
         }
 
+        // Keys typed into text fields are not shortcuts.
+        let wants_keyboard_input = ctx.wants_keyboard_input();
         let (task_dropped,
-             left_arrow,
-             right_arrow,
-             delete,
-             quit,
+             actions,
              left_button_pressed,
              left_button_down,
              left_button_released
@@ -273,67 +864,69 @@ impl<'a> eframe::App for MyApp<'a> {
                                 } else { 
                                     false 
                                 };
+                            let is_mot = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+                            let is_cvat = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xml"));
                             if is_toml {
                                 self.label_configs_filename = path.clone();
-                                self.label_task = LabelTask::new();
-                                self.label_task.load_label_configs(&self.label_configs_filename);
+                                self.reload_label_configs();
+                            } else if is_mot {
+                                if let Err(e) = self.label_task.import_mot(path, self.image_provider.as_ref(), None) {
+                                    println!("Error: Importing MOT annotations did not work ({}).", e);
+                                }
+                                self.history.clear();
+                            } else if is_cvat {
+                                if let Err(e) = self.label_task.import_cvat(path, self.image_provider.as_ref(), None) {
+                                    println!("Error: Importing CVAT annotations did not work ({}).", e);
+                                }
+                                self.history.clear();
                             } else {
                                 println!("Error: {} and dropped file was not a config file.", e.to_string());
                             }
                         } else {
                             task_dropped = true;
                             self.current_image = 0;
-                            self.label_task = LabelTask::new();
-                            self.label_task.load_label_configs(&self.label_configs_filename);
+                            self.reload_label_configs();
                         } 
                     }
                  }
 
+                 let actions = if wants_keyboard_input {
+                     vec![]
+                 } else {
+                     pressed_actions(i, &self.shortcuts)
+                 };
+
                  (task_dropped,
-                  i.key_pressed(egui::Key::ArrowLeft),
-                  i.key_pressed(egui::Key::ArrowRight),
-                  i.key_pressed(egui::Key::Delete),
-                  i.modifiers.ctrl && i.key_pressed(egui::Key::Q),
+                  actions,
                   i.pointer.button_pressed(egui::PointerButton::Primary),
                   i.pointer.button_down(egui::PointerButton::Primary),
                   i.pointer.button_released(egui::PointerButton::Primary),
                 )
              });
 
-        if self.image_provider.frame_count() > 0 {
-            if left_arrow {
-                self.current_image = (self.current_image as i32 - 1).rem_euclid(self.image_provider.frame_count() as i32) as usize;
-                // ctx.request_repaint()
-            }
-
-            if right_arrow {
-                self.current_image = ((self.current_image + 1) as i32).rem_euclid(self.image_provider.frame_count() as i32) as usize;
-                // ctx.request_repaint()
-            }
-
-            if delete {
-
+        for action in &actions {
+            // Undo and redo work without any frames.
+            if self.image_provider.frame_count() > 0 || matches!(action, KeyAction::Undo | KeyAction::Redo) {
+                self.handle_key_action(*action);
             }
+        }
 
-            if quit {
-                let result = self.label_task.save_annotations(&self.labels_filename, self.image_provider.frame_count(), true);
-                match result {
-                    Ok(_) => exit(1),
-                    Err(e) => println!("Saving annotations did not work ({}).", e),
-                }
-            }
+        if self.image_provider.frame_count() > 0 && task_dropped {
+            // self.image_stack.sort_by(|a, b| a.name.cmp(&b.name));
+            ctx.request_repaint();
+        }
 
-            if task_dropped {
-                // self.image_stack.sort_by(|a, b| a.name.cmp(&b.name));
-                ctx.request_repaint();
-            }
+        // The copy is only updated while a box of the current sequence is drawn.
+        if self.current_annotation_copy_sequence != self.label_task.current_sequence {
+            self.reload_current_annotation_copy();
         }
 
         //
         // Side panel with tools.
         egui::SidePanel::left("leftpanel").show(ctx, |ui| {
 
-            ui.label("Quit: CTRL-Q (saves annotations)");
+            let keybindings = self.label_task.configs.keybindings.clone();
+            ui.label(format!("Quit: {} (saves annotations)", keybindings.quit));
 
             let mut dummy_annotation = Annotation::new(&self.label_task.configs, 0.0, 0.0, 0);
             let ann = self.current_annotation_copy.as_mut().unwrap_or(&mut dummy_annotation);
@@ -345,35 +938,127 @@ impl<'a> eframe::App for MyApp<'a> {
                 changed |= response.changed();
                 ui.separator();
                 ui.label(RichText::new("Labels").size(15.0).strong());
-                for (label_index,lc) in self.label_task.configs.label_configs.iter().enumerate() {
+                let configs = &self.label_task.configs;
+                for (label_index,lc) in configs.label_configs.iter().enumerate() {
+                    // Labels whose condition does not hold are hidden, required labels are marked with a '*'.
+                    if !configs.is_visible(label_index, &ann.labels) {
+                        continue;
+                    }
+                    let name = if configs.is_required(label_index, &ann.labels) { format!("{} *", lc.name()) } else { lc.name().to_string() };
+                    let optional = lc.is_optional();
                     match lc {
                         LabelConfig::S(lcs) => {
-                            ui.label(format!("{}:",&lcs.name));
-                            for s in &lcs.states {
-                                if let Label::S(label) = &mut ann.labels[label_index] {
-                                    response = ui.selectable_value(&mut label.state, s.to_string(), s);
+                            ui.label(format!("{}:",&name));
+                            if let Label::S(label) = &mut ann.labels[label_index] {
+                                if optional {
+                                    response = ui.selectable_value(&mut label.state, None, "(not set)");
                                     changed |= response.changed();
-                                } else {
-                                    println!("Error: Did not find a Label::S where I expected one.");
                                 }
+                                changed |= state_tree(ui, &lcs.states, "", &mut label.state);
+                            } else {
+                                println!("Error: Did not find a Label::S where I expected one.");
                             }
                         },
                         LabelConfig::I(lci) => {
                             if let Label::I(label) = &mut ann.labels[label_index] {
-                                let title = format!("{} ({}-{})", &lci.name, lci.first, lci.last);
-                                response = ui.add(egui::Slider::new(&mut label.state, lci.first..=lci.last).text(title));
-                                changed |= response.changed();
+                                let title = format!("{} ({}-{})", &name, lci.first, lci.last);
+                                changed |= optional_value(ui, &mut label.state, lci.first, optional, &title, |ui, state| {
+                                    ui.add(egui::Slider::new(state, lci.first..=lci.last).text(&title))
+                                });
                             } else {
                                 println!("Error: Did not find a Label::I where I expected one.");
                             }
+                        },
+                        LabelConfig::B(lcb) => {
+                            if let Label::B(label) = &mut ann.labels[label_index] {
+                                changed |= optional_value(ui, &mut label.state, lcb.default, optional, &name, |ui, state| {
+                                    ui.checkbox(state, &name)
+                                });
+                            } else {
+                                println!("Error: Did not find a Label::B where I expected one.");
+                            }
+                        },
+                        LabelConfig::F(lcf) => {
+                            if let Label::F(label) = &mut ann.labels[label_index] {
+                                let title = format!("{} ({}-{})", &name, lcf.min, lcf.max);
+                                changed |= optional_value(ui, &mut label.state, lcf.min, optional, &title, |ui, state| {
+                                    let mut slider = egui::Slider::new(state, lcf.min..=lcf.max).text(&title);
+                                    if lcf.step > 0.0 {
+                                        slider = slider.step_by(lcf.step as f64);
+                                    }
+                                    ui.add(slider)
+                                });
+                            } else {
+                                println!("Error: Did not find a Label::F where I expected one.");
+                            }
+                        },
+                        LabelConfig::T(lct) => {
+                            if let Label::T(label) = &mut ann.labels[label_index] {
+                                ui.label(format!("{}:", &name));
+                                ui.horizontal(|ui| {
+                                    // Typing sets the text, even if it is empty.
+                                    let mut text = label.state.clone().unwrap_or_default();
+                                    let mut edit = egui::TextEdit::singleline(&mut text).hint_text("not set");
+                                    if let Some(max_length) = lct.max_length {
+                                        edit = edit.char_limit(max_length);
+                                    }
+                                    if ui.add(edit).changed() {
+                                        label.state = Some(text);
+                                        changed = true;
+                                    }
+                                    if optional && label.state.is_some() && unset_button(ui) {
+                                        label.state = None;
+                                        changed = true;
+                                    }
+                                });
+                                if let Some(Err(message)) = label.state.as_ref().map(|text| lct.check(text)) {
+                                    ui.colored_label(Color32::RED, message);
+                                }
+                            } else {
+                                println!("Error: Did not find a Label::T where I expected one.");
+                            }
+                        },
+                        LabelConfig::M(lcm) => {
+                            ui.label(format!("{}:",&name));
+                            if let Label::M(label) = &mut ann.labels[label_index] {
+                                ui.horizontal_wrapped(|ui| {
+                                    // "(none)" sets the label to an empty selection, as opposed to not set.
+                                    if ui.selectable_label(label.state.as_ref().is_some_and(|s| s.is_empty()), "(none)").clicked() {
+                                        label.state = Some(vec![]);
+                                        changed = true;
+                                    }
+                                    for s in &lcm.states {
+                                        let mut selected = label.state.as_ref().is_some_and(|state| state.contains(s));
+                                        if ui.toggle_value(&mut selected, s).changed() {
+                                            lcm.select(label.state.get_or_insert_with(Vec::new), s, selected);
+                                            changed = true;
+                                        }
+                                    }
+                                    if optional && label.state.is_some() && unset_button(ui) {
+                                        label.state = None;
+                                        changed = true;
+                                    }
+                                });
+                            } else {
+                                println!("Error: Did not find a Label::M where I expected one.");
+                            }
                         }
                     }
                 }
+
+                // Surface required labels that are not set yet.
+                let missing = configs.missing_labels(&ann.labels);
+                if self.label_task.has_sequences() && !missing.is_empty() {
+                    ui.colored_label(Color32::from_rgb(255, 160, 0), format!("Not set: {}", missing.join(", ")));
+                }
         
                 if changed {
+                    self.label_task.configs.reset_hidden_labels(&mut ann.labels);
                     if self.label_task.has_sequences() {
-                        self.label_task.sequences[self.label_task.current_sequence].edit_annotation(
-                            self.current_image, ann);
+                        let (frame, current) = (self.current_image, self.label_task.current_sequence);
+                        self.history.edit_sequence(&mut self.label_task, current, |s| {
+                            s.edit_labels(frame, ann);
+                        });
                     }
                 }
         
@@ -382,253 +1067,411 @@ impl<'a> eframe::App for MyApp<'a> {
 
                 if ui.button(RichText::new("Copy labels to following keyframes").small()).clicked() {
                     if self.label_task.has_sequences() {
-                        self.label_task.sequences[self.label_task.current_sequence].propagate(self.current_image);
+                        let (frame, current) = (self.current_image, self.label_task.current_sequence);
+                        self.history.edit_sequence(&mut self.label_task, current, |s| {
+                            s.propagate(frame);
+                        });
                     }
                 }
                 ui.separator();
+
+                ui.label(RichText::new("Keyframes").strong());
+                let is_keyframe = self.label_task.sequences.get(self.label_task.current_sequence)
+                    .is_some_and(|s| s.annotations.iter().any(|a| a.frame == self.current_image));
+                if ui.add_enabled(is_keyframe, egui::Button::new(RichText::new("Delete keyframe").small()))
+                    .on_hover_text(&keybindings.delete_keyframe).clicked() {
+                    self.edit_current_sequence(|s, frame| {
+                        s.delete_keyframe(frame);
+                    });
+                }
+                ui.horizontal(|ui| {
+                    if ui.button(RichText::new("Delete all before").small())
+                        .on_hover_text("Delete the keyframes before the current frame").clicked() {
+                        self.edit_current_sequence(|s, frame| {
+                            s.delete_keyframes_before(frame);
+                        });
+                    }
+                    if ui.button(RichText::new("Delete all after").small())
+                        .on_hover_text("Delete the keyframes after the current frame").clicked() {
+                        self.edit_current_sequence(|s, frame| {
+                            s.delete_keyframes_after(frame);
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let last_frame = self.image_provider.frame_count().max(1) - 1;
+                    ui.add(egui::DragValue::new(&mut self.clear_range[0]).range(0..=last_frame));
+                    ui.label("-");
+                    ui.add(egui::DragValue::new(&mut self.clear_range[1]).range(0..=last_frame));
+                    let [first, last] = self.clear_range;
+                    if ui.add_enabled(first <= last, egui::Button::new(RichText::new("Clear frames").small()))
+                        .on_hover_text("Delete the keyframes in this frame range").clicked() {
+                        self.edit_current_sequence(|s, _| {
+                            s.clear_frames(first, last);
+                        });
+                    }
+                });
+                ui.separator();
+                ui.checkbox(&mut self.show_captions, "Show captions");
+                ui.checkbox(&mut self.color_by_class, "Color by class");
+                ui.separator();
                 if ui.button("Save annotations").clicked() {
-                    self.label_task.save_annotations(&self.labels_filename, self.image_provider.frame_count(), true);
+                    if let Err(e) = self.save_annotations() {
+                        println!("Saving annotations did not work ({}).", e);
+                    }
+                }
+                if ui.button("Export COCO").clicked() {
+                    let path = self.labels_filename.with_extension("coco.json");
+                    self.export("COCO", |task, images| task.export_coco(&path, images, None, None));
+                }
+                if ui.button("Export MOT").clicked() {
+                    let path = self.labels_filename.with_extension("mot.txt");
+                    self.export("MOT", |task, images| task.export_mot(&path, images, None, None));
+                }
+                if ui.button("Export CVAT").clicked() {
+                    let path = self.labels_filename.with_extension("cvat.xml");
+                    self.export("CVAT", |task, images| task.export_cvat(&path, images, None, None));
+                }
+                if ui.button("Export VOC").clicked() {
+                    let dir = self.labels_filename.with_extension("voc");
+                    self.export("VOC", |task, images| task.export_voc(&dir, images, None, None));
+                }
+                if ui.button("Export YOLO").clicked() {
+                    // Darknet expects the label files next to the images. Videos get a directory next to the json file.
+                    let dir = match &self.images_dir {
+                        Some(dir) if dir.is_dir() => dir.clone(),
+                        _ => self.labels_filename.with_extension("yolo"),
+                    };
+                    self.export("YOLO", |task, images| task.export_yolo(&dir, images, None, None));
                 }
                 ui.horizontal(|ui| {
                     if ui.button("Add sequence").clicked() {
-                        self.label_task.sequences.push(AnnotationSequence::new());
-                        self.label_task.current_sequence = self.label_task.sequences.len() - 1;
+                        let sequence = self.label_task.new_sequence();
+                        self.history.add_sequence(&mut self.label_task, sequence);
+                    }
+                    if ui.add_enabled(self.history.can_undo(), egui::Button::new("Undo")).on_hover_text(&keybindings.undo).clicked() {
+                        self.history.undo(&mut self.label_task);
+                    }
+                    if ui.add_enabled(self.history.can_redo(), egui::Button::new("Redo")).on_hover_text(&keybindings.redo).clicked() {
+                        self.history.redo(&mut self.label_task);
                     }
                 });
                 ui.separator();
         
+                let mut to_delete = None;
                 for i in 0..self.label_task.sequences.len() {
                     ui.horizontal(|ui| {
+                        let (rect, _) = ui.allocate_exact_size(Vec2::splat(10.0), Sense::hover());
+                        let labels = self.label_task.sequences[i].get_interpolated_annotation_for_frame(self.current_image)
+                            .map(|a| a.labels).unwrap_or_default();
+                        ui.painter().rect_filled(rect, Rounding::ZERO, self.track_color(i, &labels));
                         let response = ui.selectable_label(i == self.label_task.current_sequence, format!("Annotation sequence {}", i));
                         if response.clicked() {
                             self.label_task.current_sequence = i;
                         }
+                        let incomplete = self.label_task.sequences[i].incomplete_frames(&self.label_task.configs);
+                        if !incomplete.is_empty() {
+                            ui.colored_label(Color32::from_rgb(255, 160, 0), "⚠")
+                                .on_hover_text(format!("Required labels are not set in frames {:?}", incomplete));
+                        }
                         if ui.button("Delete").clicked() {
-                            if i < self.label_task.sequences.len() {
-                                self.label_task.sequences.remove(i);
-                            }
-                            let l = self.label_task.sequences.len();
-                            if self.label_task.current_sequence >= l {
-                                self.label_task.current_sequence = if l > 0 { l - 1 } else { 0 };
-                            }
+                            to_delete = Some(i);
+                        }
+                    });
+                }
+                // Deleted after the loop, so the remaining rows still index valid sequences.
+                if let Some(i) = to_delete {
+                    self.history.delete_sequence(&mut self.label_task, i);
+                }
+        });
 
+        //
+        // Frame controls below the image.
+        egui::TopBottomPanel::bottom("frame_controls").show(ctx, |ui| {
+            if self.image_provider.frame_count() == 0 {
+                return;
+            }
+
+            self.timeline(ui);
+
+            ui.style_mut().spacing.slider_width = ui.available_width();
+            let slider = egui::Slider::new(&mut self.current_image, 0..=self.image_provider.frame_count()-1)
+                .trailing_fill(true).show_value(false);
+            ui.add(slider);
+            ui.horizontal(|ui| {
+                if ui.button("<-").clicked() {
+                    self.current_image = (self.current_image as i64 - 1).max(0) as usize;
+                }
+                if ui.button("->").clicked() {
+                    self.current_image = (self.current_image + 1).min(self.image_provider.frame_count()-1);
+                }
+                let play_text = if self.playback.playing { "Stop" } else { "Play" };
+                if ui.button(play_text).on_hover_text(&self.label_task.configs.keybindings.play_pause).clicked() {
+                    self.playback.toggle();
+                }
+                ui.label(format!("Frame: {}", self.current_image));
+
+                ui.separator();
+                ui.checkbox(&mut self.playback.reverse, "Reverse");
+                ui.checkbox(&mut self.playback.looping, "Loop");
+                egui::ComboBox::from_id_salt("playback_speed")
+                    .selected_text(format!("{}x", self.playback.speed))
+                    .width(60.0)
+                    .show_ui(ui, |ui| {
+                        for speed in PLAYBACK_SPEEDS {
+                            ui.selectable_value(&mut self.playback.speed, speed, format!("{}x", speed));
                         }
                     });
+                ui.add(egui::DragValue::new(&mut self.playback.fps).range(1..=240).suffix(" fps"));
+                if self.playback.playing && self.playback.dropped_frames > 0 {
+                    ui.label(format!("Dropped frames: {}", self.playback.dropped_frames))
+                        .on_hover_text("Loading the frames is slower than the playback rate");
+                }
+
+                ui.separator();
+                if let Some(texture) = &self.texture {
+                    let image_size = texture.size_vec2();
+                    if ui.button("Fit").on_hover_text("Show the whole image").clicked() {
+                        self.view.fit();
+                    }
+                    if ui.button("1:1").on_hover_text("One image pixel per screen pixel").clicked() {
+                        let canvas = self.canvas_rect;
+                        self.view.zoom_to(1.0 / ctx.pixels_per_point(), canvas.center(), canvas, image_size);
+                    }
+                    let zoom = self.view.image_rect(self.canvas_rect, image_size).width() / image_size.x;
+                    ui.label(format!("Zoom: {:.0}%", zoom * ctx.pixels_per_point() * 100.0));
                 }
+            });
         });
 
         //
         // Main panel with image.
         egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::both().show(ui, |ui| {
+            if let Err(e) = self.set_current_image(self.current_image, ctx) {
+                println!("Error: {}", e);
+            }
 
-                if let Err(e) = self.set_current_image(self.current_image, &ctx) {
-                    println!("Error: {}", e);
-                }
+            if let Some((texture_id, image_size)) = self.texture.as_ref().map(|t| (t.id(), t.size_vec2())) {
 
-                if let Some(texture) = &self.texture {
+                // The canvas has the size of the image shrunk to fit the panel, the view zooms into it.
+                let available = ui.available_size();
+                let scale = (available.x / image_size.x).min(available.y / image_size.y).min(1.0);
+                let (canvas, response) = ui.allocate_exact_size(image_size * scale, Sense::click_and_drag());
+                self.canvas_rect = canvas;
 
-                    let t = egui::ImageSource::Texture(SizedTexture::from_handle(texture));
-                    let response = ui.add(egui::Image::new(t).shrink_to_fit()
-                        .sense(Sense::click_and_drag()));
-                        
-                    response.context_menu(|ui| {
-                        if ui.button("New annotation sequence").clicked() {
-                                self.label_task.sequences.push(AnnotationSequence::new());
-                                self.label_task.current_sequence = self.label_task.sequences.len() - 1;
-                            ui.close_menu();
-                        }
-                    });
+                // Zoom around the cursor with the mouse wheel, pan with the middle button or space + left button.
+                let (scroll, zoom_delta, space_down) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta(), i.key_down(egui::Key::Space)));
+                if let Some(hover_pos) = response.hover_pos() {
+                    let factor = zoom_delta * (scroll / 200.0).exp();
+                    if factor != 1.0 {
+                        self.view.zoom_by(factor, hover_pos, canvas, image_size);
+                    }
+                }
+                let panning = space_down || response.dragged_by(egui::PointerButton::Middle);
+                if panning && response.dragged() {
+                    self.view.pan_by(response.drag_delta(), canvas, image_size);
+                }
 
-                    ui.style_mut().spacing.slider_width = response.rect.width();
-                    let slider = egui::Slider::new(&mut self.current_image, 0..=self.image_provider.frame_count()-1)
-                        .trailing_fill(true).show_value(false);
-                    ui.add(slider);
-                    ui.horizontal(|ui| {
-                        if ui.button("<-").clicked() {
-                            if self.image_provider.frame_count() > 0 {
-                                self.current_image = (self.current_image as i64 - 1).max(0) as usize;
-                            }
-                        }
-                        if ui.button("->").clicked() {
-                            if self.image_provider.frame_count() > 0 {
-                                self.current_image = (self.current_image + 1).min(self.image_provider.frame_count()-1);
-                            }
-                        }
-                        if self.play_mode {
-                            if ui.button("Stop").clicked() {
-                                self.play_mode = false;
-                            }
-                        } else {
-                            if ui.button("Play").clicked() {
-                                self.play_mode = true;
-                            }
-                        }
-                        ui.label(format!("Frame: {}", self.current_image));
-                    });
+                let image_rect = self.view.image_rect(canvas, image_size);
+                let painter = ui.painter_at(canvas);
+                painter.image(texture_id, image_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::WHITE);
 
-                    //
-                    // Select the currently active annotation sequence:
-                    if response.clicked() {
-                        if let Some(pp) = response.interact_pointer_pos() {
-                            let p = normalized_pos(pp, &response);
-                            let closest_sequence_ = self.label_task.get_closest_annotation_sequence(self.current_image, p.x, p.y, true);
+                response.context_menu(|ui| {
+                    if ui.button("New annotation sequence").clicked() {
+                        let sequence = self.label_task.new_sequence();
+                        self.history.add_sequence(&mut self.label_task, sequence);
+                        ui.close_menu();
+                    }
 
-                            if let Some((closest_sequence, distance, _contains_point)) = closest_sequence_ {
-                                self.label_task.current_sequence = closest_sequence;
-                                // println!("Selected sequence {}", closest_sequence);
-                            }
-                        }
+                    let current = self.label_task.current_sequence;
+                    let can_split = self.label_task.sequences.get(current)
+                        .is_some_and(|s| s.annotations.first().is_some_and(|a| a.frame < self.current_image));
+                    if ui.add_enabled(can_split, egui::Button::new(format!("Split sequence {} here", current)))
+                        .on_hover_text("The current sequence ends before this frame, a new sequence continues from here").clicked() {
+                        self.history.split_sequence(&mut self.label_task, current, self.current_image);
+                        ui.close_menu();
                     }
 
-                    
-                    // Select an action when the left button is pressed:
-                    if left_button_pressed {
-                        // println!("Left button pressed");
-        
-                        if let Some(pp) = response.interact_pointer_pos() {
-                            let p = normalized_pos(pp, &response);
-
-                            let annotation_ = self.label_task.get_current_interpolated_annotation_for_frame(self.current_image);
-                            
-                            let mut action: Option<ActionType> = None;
-
-                            if let Some(annotation) = &annotation_ {
-
-                                let (corner, corner_dist, _corner_point) = annotation.closest_corner_point(p.x, p.y);
-                                if corner_dist * response.rect.width() < MyApp::CORNER_CATCH_RADIUS {
-                                    action = Some(ActionType::ModifyCorner(corner));
-                                } else {
-                                    let bbox: Aabb = (&annotation.bbox).into();
-                                    if bbox.contains_local_point(&Point::new(p.x, p.y)) {
-                                        action = Some(ActionType::Move(p));
+                    ui.add_enabled_ui(self.label_task.sequences.len() > 1, |ui| {
+                        ui.menu_button(format!("Merge into sequence {}", current), |ui| {
+                            for other in (0..self.label_task.sequences.len()).filter(|i| *i != current) {
+                                if ui.button(format!("Annotation sequence {}", other)).clicked() {
+                                    if let Some(dropped) = self.history.merge_sequences(&mut self.label_task, current, other) {
+                                        if dropped > 0 {
+                                            println!("Merging dropped {} keyframes of sequence {} where both sequences were visible.", dropped, other);
+                                        }
                                     }
+                                    ui.close_menu();
                                 }
                             }
-            
-                            if action.is_none() && annotation_.is_none() {
-                                // If there is a sequence, but the current sequence has no annotations yet, create an annotation for it.
-                                if self.label_task.has_sequences() && self.label_task.sequences[self.label_task.current_sequence].annotations.is_empty() {
-                                    action = Some(ActionType::New);
-                                    self.label_task.sequences[self.label_task.current_sequence].annotations.push(Annotation::new(
-                                        &self.label_task.configs, 0.0, 0.0, self.current_image));
-                    
-                                    self.with_current_annotation(|a| {
-                                        a.invisible = false;
-                                    });
-                                }
-                            }
+                        });
+                    });
+                });
 
-                            self.annotation_action.action_type = action.unwrap_or(self.annotation_action.action_type);
+                //
+                // Select the currently active annotation sequence:
+                if response.clicked() && !space_down {
+                    if let Some(pp) = response.interact_pointer_pos() {
+                        let p = normalized_pos(pp, image_rect);
+                        let closest_sequence_ = self.label_task.get_closest_annotation_sequence(self.current_image, p.x, p.y, true);
+
+                        if let Some((closest_sequence, _distance, _contains_point)) = closest_sequence_ {
+                            self.label_task.current_sequence = closest_sequence;
+                            // println!("Selected sequence {}", closest_sequence);
                         }
                     }
-        
-                    if left_button_released {
-                        self.annotation_action.action_type = ActionType::None;
-                    }
-        
+                }
 
-                    // if response.clicked() {
-                    //     let p = response.interact_pointer_pos().unwrap();
-                    //     // println!("{:?}", p);
-                    //     let p = normalized_pos(p, &response);
-                    //     // println!("{:.3}, {:.3}", p.x, p.y);
-                    // }
-
-                    if response.drag_started() {
-                        let p = response.interact_pointer_pos().unwrap();
-                        // println!("{:?}", p);
-                        let p = normalized_pos(p, &response);
-                        // println!("Drag starting: {:.3}, {:.3}", p.x, p.y);
-                        self.drag_start_position = p;
-                    }
+                
+                // Select an action when the left button is pressed:
+                if left_button_pressed && !space_down {
+                    // println!("Left button pressed");
+    
+                    if let Some(pp) = response.interact_pointer_pos() {
+                        let p = normalized_pos(pp, image_rect);
 
-                    if response.dragged() {
-                        let p = response.interact_pointer_pos().unwrap();
-                        // println!("{:?}", p);
-                        let p = normalized_pos(p, &response);
+                        // Everything that happens until the button is released is undone in one step.
+                        self.history.begin(&self.label_task, self.label_task.current_sequence);
 
-                        // println!("Dragged; action type: {:?}", self.annotation_action.action_type);
-                        match &self.annotation_action.action_type {
-                            ActionType::New => {
-                                self.with_current_annotation(|a| {
-                                    a.bbox.mins = SerializablePoint::new(p.x, p.y);
-                                });
-                                self.annotation_action.action_type = ActionType::ModifyCorner(BoxCorner::RD);
-                            },
-                            ActionType::None => {},
-                            ActionType::ModifyCorner(c) => {
-                                let cc = c.clone();
-                                self.with_current_annotation(|a| {
-                                    match cc {
-                                        BoxCorner::LU => {
-                                            a.bbox.mins = SerializablePoint::new(a.bbox.maxs.x.min(p.x), a.bbox.maxs.y.min(p.y));
-                                        },
-                                        BoxCorner::RU => {
-                                            a.bbox.maxs.x = a.bbox.mins.x.max(p.x);
-                                            a.bbox.mins.y = a.bbox.maxs.y.min(p.y);
-                                        },
-                                        BoxCorner::RD => {
-                                            a.bbox.maxs = SerializablePoint::new(a.bbox.mins.x.max(p.x), a.bbox.mins.y.max(p.y));
-                                        },
-                                        BoxCorner::LD => {
-                                            a.bbox.mins.x = a.bbox.maxs.x.min(p.x);
-                                            a.bbox.maxs.y = a.bbox.mins.y.max(p.y);
-                                        }
-                                    }
-                                   
-                                });
-                            },
-                            ActionType::Move(old_p) => {
-                                let delta = p - old_p.clone(); 
+                        let annotation_ = self.label_task.get_current_interpolated_annotation_for_frame(self.current_image);
+                        
+                        let mut action: Option<ActionType> = None;
+
+                        if let Some(annotation) = &annotation_ {
+
+                            let (corner, corner_dist, _corner_point) = annotation.closest_corner_point(p.x, p.y);
+                            if corner_dist * image_rect.width() < MyApp::CORNER_CATCH_RADIUS {
+                                action = Some(ActionType::ModifyCorner(corner));
+                            } else {
+                                let bbox: Aabb = (&annotation.bbox).into();
+                                if bbox.contains_local_point(&Point::new(p.x, p.y)) {
+                                    action = Some(ActionType::Move(p));
+                                }
+                            }
+                        }
+        
+                        if action.is_none() && annotation_.is_none() {
+                            // If there is a sequence, but the current sequence has no annotations yet, create an annotation for it.
+                            if self.label_task.has_sequences() && self.label_task.sequences[self.label_task.current_sequence].annotations.is_empty() {
+                                action = Some(ActionType::New);
+                                self.label_task.sequences[self.label_task.current_sequence].annotations.push(Annotation::new(
+                                    &self.label_task.configs, 0.0, 0.0, self.current_image));
+                
                                 self.with_current_annotation(|a| {
-                                    a.bbox.mins.x += delta.x;
-                                    a.bbox.mins.y += delta.y;
-                                    a.bbox.maxs.x += delta.x;
-                                    a.bbox.maxs.y += delta.y;
+                                    a.invisible = false;
                                 });
-                                self.annotation_action.action_type = ActionType::Move(p);
                             }
                         }
+
+                        self.annotation_action.action_type = action.unwrap_or(self.annotation_action.action_type);
                     }
+                }
+    
+                if left_button_released {
+                    self.annotation_action.action_type = ActionType::None;
+                    self.history.commit(&self.label_task);
+                }
+    
 
-                    if response.drag_stopped() {
+                // if response.clicked() {
+                //     let p = response.interact_pointer_pos().unwrap();
+                //     // println!("{:?}", p);
+                //     let p = normalized_pos(p, &response);
+                //     // println!("{:.3}, {:.3}", p.x, p.y);
+                // }
+
+                if response.drag_started_by(egui::PointerButton::Primary) && !panning {
+                    let p = response.interact_pointer_pos().unwrap();
+                    // println!("{:?}", p);
+                    let p = normalized_pos(p, image_rect);
+                    // println!("Drag starting: {:.3}, {:.3}", p.x, p.y);
+                    self.drag_start_position = p;
+                }
 
-                        match self.annotation_action.action_type {
-                            ActionType::None => {},
-                            ActionType::Move(_) => {},
-                            ActionType::New => {},
-                            ActionType::ModifyCorner(_) => {}
+                if response.dragged_by(egui::PointerButton::Primary) && !panning {
+                    let p = response.interact_pointer_pos().unwrap();
+                    // println!("{:?}", p);
+                    let p = normalized_pos(p, image_rect);
+
+                    // println!("Dragged; action type: {:?}", self.annotation_action.action_type);
+                    match &self.annotation_action.action_type {
+                        ActionType::New => {
+                            self.with_current_annotation(|a| {
+                                a.bbox.mins = SerializablePoint::new(p.x, p.y);
+                            });
+                            self.annotation_action.action_type = ActionType::ModifyCorner(BoxCorner::RD);
+                        },
+                        ActionType::None => {},
+                        ActionType::ModifyCorner(c) => {
+                            let cc = *c;
+                            self.with_current_annotation(|a| {
+                                match cc {
+                                    BoxCorner::LU => {
+                                        a.bbox.mins = SerializablePoint::new(a.bbox.maxs.x.min(p.x), a.bbox.maxs.y.min(p.y));
+                                    },
+                                    BoxCorner::RU => {
+                                        a.bbox.maxs.x = a.bbox.mins.x.max(p.x);
+                                        a.bbox.mins.y = a.bbox.maxs.y.min(p.y);
+                                    },
+                                    BoxCorner::RD => {
+                                        a.bbox.maxs = SerializablePoint::new(a.bbox.mins.x.max(p.x), a.bbox.mins.y.max(p.y));
+                                    },
+                                    BoxCorner::LD => {
+                                        a.bbox.mins.x = a.bbox.maxs.x.min(p.x);
+                                        a.bbox.maxs.y = a.bbox.mins.y.max(p.y);
+                                    }
+                                }
+                               
+                            });
+                        },
+                        ActionType::Move(old_p) => {
+                            let delta = p - *old_p; 
+                            self.with_current_annotation(|a| {
+                                a.bbox.mins.x += delta.x;
+                                a.bbox.mins.y += delta.y;
+                                a.bbox.maxs.x += delta.x;
+                                a.bbox.maxs.y += delta.y;
+                            });
+                            self.annotation_action.action_type = ActionType::Move(p);
                         }
                     }
+                }
 
-                    //
-                    // Draw visible boxes
-
-                    let anns = self.label_task.get_all_interpolated_annotations_for_frame(self.current_image);
+                if response.drag_stopped() {
 
-                    for a_ in &anns {
-                        let (i, a) = a_;
-                        if *i == self.label_task.current_sequence {
-                            self.current_annotation_copy = Some(a.clone());
-                            draw_annotation(&response, ui, a, true);
-                        } else {
-                            draw_annotation(&response, ui, a, false);
-                        }
+                    match self.annotation_action.action_type {
+                        ActionType::None => {},
+                        ActionType::Move(_) => {},
+                        ActionType::New => {},
+                        ActionType::ModifyCorner(_) => {}
                     }
+                }
 
-                    ctx.send_viewport_cmd(egui::ViewportCommand::Title(self.images_dir.clone().unwrap_or(PathBuf::new()).to_string_lossy().to_string()));
+                //
+                // Draw visible boxes
 
-                    if self.play_mode {
-                        self.current_image = (self.current_image + 1).min(self.image_provider.frame_count() - 1);
-                        if self.current_image >= self.image_provider.frame_count() - 1 {
-                            self.play_mode = false;
-                        }
-                        ctx.request_repaint();
+                let anns = self.label_task.get_all_interpolated_annotations_for_frame(self.current_image);
+
+                for a_ in &anns {
+                    let (i, a) = a_;
+                    let color = self.track_color(*i, &a.labels);
+                    let caption = self.show_captions.then(|| annotation_caption(*i, a, &self.label_task.configs.missing_labels(&a.labels)));
+                    if *i == self.label_task.current_sequence {
+                        self.current_annotation_copy = Some(a.clone());
+                        draw_annotation(&response, &painter, image_rect, a, true, color, caption);
+                    } else {
+                        draw_annotation(&response, &painter, image_rect, a, false, color, caption);
                     }
                 }
-            });
-           
+
+                ctx.send_viewport_cmd(egui::ViewportCommand::Title(self.images_dir.clone().unwrap_or_default().to_string_lossy().to_string()));
+
+                if self.playback.playing {
+                    let now = Instant::now();
+                    self.current_image = self.playback.advance(now, self.current_image, self.image_provider.frame_count());
+                    ctx.request_repaint_after(self.playback.time_to_next_frame(now));
+                }
+            }
         });
     }
 }